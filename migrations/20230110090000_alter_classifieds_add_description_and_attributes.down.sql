@@ -0,0 +1,3 @@
+ALTER TABLE classifieds
+    DROP COLUMN description,
+    DROP COLUMN attributes;
@@ -0,0 +1,3 @@
+ALTER TABLE classifieds
+    ADD COLUMN description TEXT,
+    ADD COLUMN attributes JSONB NOT NULL DEFAULT '[]';
@@ -0,0 +1,4 @@
+DROP TYPE building_material;
+DROP TYPE parking;
+DROP TYPE furnishing;
+DROP TYPE heating_type;
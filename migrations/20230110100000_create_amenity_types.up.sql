@@ -0,0 +1,4 @@
+CREATE TYPE heating_type AS ENUM ('central', 'district', 'none');
+CREATE TYPE furnishing AS ENUM ('furnished', 'semi_furnished', 'unfurnished');
+CREATE TYPE parking AS ENUM ('garage', 'underground', 'spot', 'none');
+CREATE TYPE building_material AS ENUM ('brick', 'concrete', 'bca', 'wood', 'other');
@@ -0,0 +1,7 @@
+ALTER TABLE classifieds
+    DROP COLUMN balcony_count,
+    DROP COLUMN building_material,
+    DROP COLUMN elevator,
+    DROP COLUMN furnishing,
+    DROP COLUMN heating,
+    DROP COLUMN parking;
@@ -0,0 +1,7 @@
+ALTER TABLE classifieds
+    ADD COLUMN balcony_count smallint,
+    ADD COLUMN building_material building_material,
+    ADD COLUMN elevator boolean,
+    ADD COLUMN furnishing furnishing,
+    ADD COLUMN heating heating_type,
+    ADD COLUMN parking parking;
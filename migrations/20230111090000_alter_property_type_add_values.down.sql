@@ -0,0 +1,14 @@
+-- Postgres can't drop enum values, the type is recreated and the new values
+-- folded back into the closest old one.
+ALTER TYPE property_type RENAME TO property_type_old;
+CREATE TYPE property_type AS ENUM ('apartment', 'house');
+ALTER TABLE classifieds
+    ALTER COLUMN property_type TYPE property_type
+    USING (
+        CASE property_type::text
+            WHEN 'house' THEN 'house'
+            WHEN 'land' THEN 'house'
+            ELSE 'apartment'
+        END
+    )::property_type;
+DROP TYPE property_type_old;
@@ -0,0 +1,7 @@
+ALTER TYPE property_type ADD VALUE 'studio';
+ALTER TYPE property_type ADD VALUE 'room';
+ALTER TYPE property_type ADD VALUE 'duplex';
+ALTER TYPE property_type ADD VALUE 'penthouse';
+ALTER TYPE property_type ADD VALUE 'land';
+ALTER TYPE property_type ADD VALUE 'commercial';
+ALTER TYPE property_type ADD VALUE 'office';
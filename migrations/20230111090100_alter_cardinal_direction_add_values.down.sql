@@ -0,0 +1,13 @@
+-- Postgres can't drop enum values, the type is recreated and the composite
+-- directions are forgotten.
+ALTER TYPE cardinal_direction RENAME TO cardinal_direction_old;
+CREATE TYPE cardinal_direction AS ENUM ('north', 'south', 'east', 'west');
+ALTER TABLE classifieds
+    ALTER COLUMN orientation TYPE cardinal_direction
+    USING (
+        CASE
+            WHEN orientation::text IN ('north', 'south', 'east', 'west')
+            THEN orientation::text
+        END
+    )::cardinal_direction;
+DROP TYPE cardinal_direction_old;
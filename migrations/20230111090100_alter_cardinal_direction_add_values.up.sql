@@ -0,0 +1,4 @@
+ALTER TYPE cardinal_direction ADD VALUE 'north_east';
+ALTER TYPE cardinal_direction ADD VALUE 'south_east';
+ALTER TYPE cardinal_direction ADD VALUE 'south_west';
+ALTER TYPE cardinal_direction ADD VALUE 'north_west';
@@ -0,0 +1,3 @@
+ALTER TABLE classifieds ADD COLUMN orientation cardinal_direction;
+UPDATE classifieds SET orientation = orientations[1];
+ALTER TABLE classifieds DROP COLUMN orientations;
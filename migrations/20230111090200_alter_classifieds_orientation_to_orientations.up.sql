@@ -0,0 +1,3 @@
+ALTER TABLE classifieds ADD COLUMN orientations cardinal_direction[] NOT NULL DEFAULT '{}';
+UPDATE classifieds SET orientations = ARRAY[orientation] WHERE orientation IS NOT NULL;
+ALTER TABLE classifieds DROP COLUMN orientation;
@@ -0,0 +1 @@
+DROP TYPE extraction_issue_severity;
@@ -0,0 +1 @@
+CREATE TYPE extraction_issue_severity AS ENUM ('warning', 'error', 'fatal');
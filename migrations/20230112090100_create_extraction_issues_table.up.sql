@@ -0,0 +1,17 @@
+CREATE TABLE extraction_issues (
+    id          BIGINT                     GENERATED ALWAYS AS IDENTITY,
+    session     uuid                       NOT NULL,
+    url         TEXT                       NOT NULL,
+    field       TEXT,
+    severity    extraction_issue_severity  NOT NULL,
+    message     TEXT                       NOT NULL,
+    created_at  TIMESTAMPTZ                NOT NULL,
+
+    PRIMARY KEY(id),
+    CONSTRAINT fk_session_url_page
+        FOREIGN KEY(session, url)
+            REFERENCES pages(session, url)
+            ON UPDATE CASCADE
+);
+
+CREATE INDEX extraction_issues_session_url_idx ON extraction_issues (session, url);
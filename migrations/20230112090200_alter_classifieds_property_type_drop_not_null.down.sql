@@ -0,0 +1,2 @@
+DELETE FROM classifieds WHERE property_type IS NULL;
+ALTER TABLE classifieds ALTER COLUMN property_type SET NOT NULL;
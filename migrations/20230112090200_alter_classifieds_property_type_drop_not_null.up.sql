@@ -0,0 +1 @@
+ALTER TABLE classifieds ALTER COLUMN property_type DROP NOT NULL;
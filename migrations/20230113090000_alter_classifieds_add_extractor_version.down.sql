@@ -0,0 +1,12 @@
+DELETE FROM classifieds AS c
+WHERE revision < (
+    SELECT MAX(revision)
+    FROM classifieds AS l
+    WHERE l.session=c.session
+        AND l.url=c.url
+);
+
+ALTER TABLE classifieds DROP CONSTRAINT classifieds_pkey;
+ALTER TABLE classifieds ADD PRIMARY KEY(session, url);
+
+ALTER TABLE classifieds DROP COLUMN extractor_version;
@@ -0,0 +1,5 @@
+ALTER TABLE classifieds ADD COLUMN extractor_version SMALLINT NOT NULL DEFAULT 1;
+ALTER TABLE classifieds ALTER COLUMN extractor_version DROP DEFAULT;
+
+ALTER TABLE classifieds DROP CONSTRAINT classifieds_pkey;
+ALTER TABLE classifieds ADD PRIMARY KEY(session, url, revision);
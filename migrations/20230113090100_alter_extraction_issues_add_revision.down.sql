@@ -0,0 +1,3 @@
+ALTER TABLE extraction_issues
+    DROP COLUMN revision,
+    DROP COLUMN extractor_version;
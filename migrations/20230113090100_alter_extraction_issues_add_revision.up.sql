@@ -0,0 +1,6 @@
+ALTER TABLE extraction_issues
+    ADD COLUMN revision SMALLINT NOT NULL DEFAULT 1,
+    ADD COLUMN extractor_version SMALLINT NOT NULL DEFAULT 1;
+ALTER TABLE extraction_issues
+    ALTER COLUMN revision DROP DEFAULT,
+    ALTER COLUMN extractor_version DROP DEFAULT;
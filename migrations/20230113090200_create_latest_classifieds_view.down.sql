@@ -0,0 +1 @@
+DROP VIEW latest_classifieds;
@@ -0,0 +1,5 @@
+CREATE VIEW latest_classifieds AS
+SELECT DISTINCT ON (session, url)
+    *
+FROM classifieds
+ORDER BY session, url, revision DESC;
@@ -0,0 +1,2 @@
+-- The list page URL the session was crawled from, NULL for older sessions.
+ALTER TABLE sessions ADD COLUMN search TEXT;
@@ -0,0 +1 @@
+DROP FUNCTION ad_id_from_url(TEXT);
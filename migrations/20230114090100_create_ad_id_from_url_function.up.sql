@@ -0,0 +1,12 @@
+-- Canonical ad ID, the same across sessions and URL variations of an ad,
+-- e.g. https://www.olx.ro/d/oferta/garsoniera-uzina-2-IDgC0Kq.html is olx:gC0Kq.
+CREATE FUNCTION ad_id_from_url(url TEXT) RETURNS TEXT
+LANGUAGE SQL IMMUTABLE STRICT
+AS $$
+    SELECT
+        CASE
+            WHEN url ~ '^https://(www\.)?olx\.ro/' THEN 'olx:'
+            WHEN url ~ '^https://(www\.)?storia\.ro/' THEN 'storia:'
+        END
+        || substring(url FROM '-ID([A-Za-z0-9]+)\.html')
+$$;
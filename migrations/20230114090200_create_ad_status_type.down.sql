@@ -0,0 +1 @@
+DROP TYPE ad_status;
@@ -0,0 +1 @@
+CREATE TYPE ad_status AS ENUM ('active', 'removed', 'expired');
@@ -0,0 +1,22 @@
+CREATE TABLE ads (
+    ad_id               TEXT          NOT NULL,
+    search              TEXT          NOT NULL,
+    url                 TEXT          NOT NULL,
+    page_type           page_type     NOT NULL,
+    status              ad_status     NOT NULL,
+    first_seen_session  uuid          NOT NULL,
+    first_seen_at       TIMESTAMPTZ   NOT NULL,
+    last_seen_session   uuid          NOT NULL,
+    last_seen_at        TIMESTAMPTZ   NOT NULL,
+    removed_at          TIMESTAMPTZ,
+
+    PRIMARY KEY(ad_id),
+    CONSTRAINT fk_first_seen_session
+        FOREIGN KEY(first_seen_session)
+            REFERENCES sessions(session),
+    CONSTRAINT fk_last_seen_session
+        FOREIGN KEY(last_seen_session)
+            REFERENCES sessions(session)
+);
+
+CREATE INDEX ads_search_status_idx ON ads (search, status);
@@ -0,0 +1 @@
+DROP VIEW classified_changes;
@@ -0,0 +1 @@
+DROP TYPE currency;
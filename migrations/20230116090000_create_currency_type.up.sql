@@ -0,0 +1 @@
+CREATE TYPE currency AS ENUM ('EUR', 'RON', 'USD');
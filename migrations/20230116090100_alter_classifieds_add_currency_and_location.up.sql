@@ -0,0 +1,12 @@
+ALTER TABLE classifieds
+    ADD COLUMN currency currency,
+    ADD COLUMN city TEXT,
+    ADD COLUMN latitude DOUBLE PRECISION,
+    ADD COLUMN longitude DOUBLE PRECISION;
+
+-- `*` is expanded when the view is created, pick up the new columns.
+CREATE OR REPLACE VIEW latest_classifieds AS
+SELECT DISTINCT ON (session, url)
+    *
+FROM classifieds
+ORDER BY session, url, revision DESC;
@@ -0,0 +1 @@
+ALTER TABLE ads DROP COLUMN property_cluster_id;
@@ -0,0 +1,5 @@
+-- Ads of the same property (cross posted, reposted) share the cluster ID, the
+-- ad ID of one of them. NULL until the ads are clustered.
+ALTER TABLE ads ADD COLUMN property_cluster_id TEXT;
+
+CREATE INDEX ads_property_cluster_id_idx ON ads (property_cluster_id);
@@ -0,0 +1 @@
+DROP VIEW properties;
@@ -0,0 +1,12 @@
+-- One row per property, count these instead of ads. Ads not clustered yet
+-- stand for themselves.
+CREATE VIEW properties AS
+SELECT
+    COALESCE(property_cluster_id, ad_id) AS property_id,
+    COUNT(*) AS ad_count,
+    array_agg(ad_id ORDER BY ad_id) AS ad_ids,
+    bool_or(status = 'active') AS active,
+    MIN(first_seen_at) AS first_seen_at,
+    MAX(last_seen_at) AS last_seen_at
+FROM ads
+GROUP BY COALESCE(property_cluster_id, ad_id);
@@ -0,0 +1,5 @@
+DELETE FROM pages WHERE content IS NULL;
+
+ALTER TABLE pages
+    DROP COLUMN pruned_at,
+    ALTER COLUMN content SET NOT NULL;
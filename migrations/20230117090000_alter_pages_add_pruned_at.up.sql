@@ -0,0 +1,4 @@
+-- Pruned pages keep their row, the classifieds reference it, but not the HTML.
+ALTER TABLE pages
+    ALTER COLUMN content DROP NOT NULL,
+    ADD COLUMN pruned_at TIMESTAMPTZ;
@@ -0,0 +1 @@
+DROP TYPE session_status;
@@ -0,0 +1,9 @@
+CREATE TYPE session_status AS ENUM (
+    'created',
+    'crawling',
+    'crawled',
+    'extracting',
+    'extracted',
+    'failed',
+    'aborted'
+);
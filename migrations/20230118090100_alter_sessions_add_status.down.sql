@@ -0,0 +1,8 @@
+ALTER TABLE sessions
+    DROP COLUMN status,
+    DROP COLUMN status_reason,
+    DROP COLUMN crawling_at,
+    DROP COLUMN extracting_at,
+    DROP COLUMN extracted_at,
+    DROP COLUMN failed_at,
+    DROP COLUMN aborted_at;
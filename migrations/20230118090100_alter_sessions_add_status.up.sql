@@ -0,0 +1,39 @@
+-- Every status has the timestamp of the last transition to it, crawled_at
+-- already exists. status_reason explains failed and aborted sessions.
+ALTER TABLE sessions
+    ADD COLUMN status session_status NOT NULL DEFAULT 'created',
+    ADD COLUMN status_reason TEXT,
+    ADD COLUMN crawling_at TIMESTAMPTZ,
+    ADD COLUMN extracting_at TIMESTAMPTZ,
+    ADD COLUMN extracted_at TIMESTAMPTZ,
+    ADD COLUMN failed_at TIMESTAMPTZ,
+    ADD COLUMN aborted_at TIMESTAMPTZ;
+
+UPDATE sessions s
+SET
+    crawling_at=s.created_at,
+    status=CASE
+        WHEN s.crawled_at IS NULL THEN 'crawling'
+        WHEN EXISTS (SELECT 1 FROM classifieds c WHERE c.session=s.session)
+            AND NOT EXISTS (
+                SELECT 1
+                FROM pages p
+                WHERE
+                    p.session=s.session
+                    AND p.page_type <> 'olx_list'
+                    AND NOT EXISTS (
+                        SELECT 1 FROM classifieds c WHERE c.session=p.session AND c.url=p.url
+                    )
+                    AND NOT EXISTS (
+                        SELECT 1
+                        FROM extraction_issues i
+                        WHERE i.session=p.session AND i.url=p.url AND i.severity='fatal'
+                    )
+            )
+            THEN 'extracted'
+        ELSE 'crawled'
+    END::session_status;
+
+UPDATE sessions
+SET extracted_at=(SELECT MAX(c.extracted_at) FROM classifieds c WHERE c.session=sessions.session)
+WHERE status='extracted';
@@ -0,0 +1 @@
+DROP TYPE schedule_run_status;
@@ -0,0 +1 @@
+CREATE TYPE schedule_run_status AS ENUM ('running', 'completed', 'failed', 'skipped');
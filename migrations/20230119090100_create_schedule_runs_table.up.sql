@@ -0,0 +1,22 @@
+-- Every tick of the daemon's schedules, skipped ones included (another run of
+-- the same search was still going).
+CREATE TABLE schedule_runs (
+    id              BIGSERIAL             NOT NULL,
+    name            TEXT                  NOT NULL,
+    search          TEXT                  NOT NULL,
+    scheduled_at    TIMESTAMPTZ           NOT NULL,
+    started_at      TIMESTAMPTZ           NOT NULL,
+    finished_at     TIMESTAMPTZ,
+    status          schedule_run_status   NOT NULL,
+    session         uuid,
+    error           TEXT,
+    pruned_pages    BIGINT,
+
+    PRIMARY KEY(id),
+    CONSTRAINT fk_session
+        FOREIGN KEY(session)
+            REFERENCES sessions(session)
+            ON DELETE SET NULL
+);
+
+CREATE INDEX schedule_runs_search_idx ON schedule_runs (search, scheduled_at);
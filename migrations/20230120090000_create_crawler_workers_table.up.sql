@@ -0,0 +1,17 @@
+-- Every process crawling a session, the one that started it and the ones that
+-- joined it. A worker that stops beating is presumed dead.
+CREATE TABLE crawler_workers (
+    worker          uuid          NOT NULL,
+    session         uuid          NOT NULL,
+    hostname        TEXT          NOT NULL,
+    pid             INTEGER       NOT NULL,
+    started_at      TIMESTAMPTZ   NOT NULL,
+    heartbeat_at    TIMESTAMPTZ   NOT NULL,
+    stopped_at      TIMESTAMPTZ,
+
+    PRIMARY KEY(worker),
+    CONSTRAINT fk_session
+        FOREIGN KEY(session)
+            REFERENCES sessions(session)
+            ON DELETE CASCADE
+);
@@ -0,0 +1,4 @@
+ALTER TABLE crawler_queue
+    DROP CONSTRAINT fk_claimed_by,
+    DROP COLUMN claimed_by,
+    DROP COLUMN lease_until;
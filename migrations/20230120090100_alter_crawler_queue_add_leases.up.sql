@@ -0,0 +1,9 @@
+-- A claimed job belongs to its worker until the lease runs out, then any
+-- worker may claim it again.
+ALTER TABLE crawler_queue
+    ADD COLUMN claimed_by uuid,
+    ADD COLUMN lease_until TIMESTAMPTZ,
+    ADD CONSTRAINT fk_claimed_by
+        FOREIGN KEY(claimed_by)
+            REFERENCES crawler_workers(worker)
+            ON DELETE SET NULL;
@@ -0,0 +1,4 @@
+DROP INDEX crawler_queue_claim_idx;
+
+ALTER TABLE crawler_queue
+    DROP COLUMN priority;
@@ -0,0 +1,7 @@
+-- Jobs are claimed by ascending priority, then by when they are due.
+ALTER TABLE crawler_queue
+    ADD COLUMN priority SMALLINT NOT NULL DEFAULT 0;
+
+CREATE INDEX crawler_queue_claim_idx
+    ON crawler_queue (session, priority, not_before, added_at)
+    WHERE status IN ('new', 'retrying');
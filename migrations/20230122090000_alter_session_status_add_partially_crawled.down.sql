@@ -0,0 +1,21 @@
+-- Enum values cannot be dropped, the type is recreated without it.
+ALTER TYPE session_status RENAME TO session_status_old;
+CREATE TYPE session_status AS ENUM (
+    'created',
+    'crawling',
+    'crawled',
+    'extracting',
+    'extracted',
+    'failed',
+    'aborted'
+);
+
+ALTER TABLE sessions ALTER COLUMN status DROP DEFAULT;
+ALTER TABLE sessions
+    ALTER COLUMN status TYPE session_status
+    USING (
+        CASE WHEN status='partially_crawled' THEN 'crawled' ELSE status::TEXT END
+    )::session_status;
+ALTER TABLE sessions ALTER COLUMN status SET DEFAULT 'created';
+
+DROP TYPE session_status_old;
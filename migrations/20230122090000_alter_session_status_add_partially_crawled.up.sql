@@ -0,0 +1,2 @@
+-- The crawl stopped on one of its budget limits, with jobs left in the queue.
+ALTER TYPE session_status ADD VALUE 'partially_crawled' AFTER 'crawled';
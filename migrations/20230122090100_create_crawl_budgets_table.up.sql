@@ -0,0 +1,18 @@
+-- Limits of the current crawl of a session, enforced by every worker, and
+-- what it used so far. Sessions crawled without limits have none.
+CREATE TABLE crawl_budgets (
+    session                     uuid          NOT NULL,
+    max_item_pages              BIGINT,
+    max_bytes                   BIGINT,
+    max_duration_seconds        BIGINT,
+    max_consecutive_failures    BIGINT,
+    started_at                  TIMESTAMPTZ   NOT NULL,
+    downloaded_bytes            BIGINT        NOT NULL DEFAULT 0,
+    consecutive_failures        BIGINT        NOT NULL DEFAULT 0,
+
+    PRIMARY KEY(session),
+    CONSTRAINT fk_session
+        FOREIGN KEY(session)
+            REFERENCES sessions(session)
+            ON DELETE CASCADE
+);
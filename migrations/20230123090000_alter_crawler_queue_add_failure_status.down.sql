@@ -0,0 +1,2 @@
+ALTER TABLE crawler_queue
+    DROP COLUMN failure_status;
@@ -0,0 +1,3 @@
+-- The HTTP status a job failed with, a 404 or 410 means the page is gone.
+ALTER TABLE crawler_queue
+    ADD COLUMN failure_status SMALLINT;
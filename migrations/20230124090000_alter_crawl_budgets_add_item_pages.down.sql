@@ -0,0 +1,2 @@
+ALTER TABLE crawl_budgets
+    DROP COLUMN item_pages;
@@ -0,0 +1,4 @@
+-- Item pages saved by the crawl the budget belongs to, like the other usage
+-- counters it restarts whenever a budget is set.
+ALTER TABLE crawl_budgets
+    ADD COLUMN item_pages BIGINT NOT NULL DEFAULT 0;
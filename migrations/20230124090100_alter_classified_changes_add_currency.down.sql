@@ -0,0 +1,63 @@
+DROP MATERIALIZED VIEW classified_changes;
+
+-- Field level differences between consecutive observations of the same ad,
+-- one row per changed field, observations ordered by session start.
+CREATE MATERIALIZED VIEW classified_changes AS
+WITH observations AS (
+    SELECT
+        ad_id_from_url(c.url) AS ad_id,
+        c.session,
+        c.url,
+        s.created_at AS observed_at,
+        LAG(c.session) OVER w AS previous_session,
+        c.price::TEXT AS price,
+        LAG(c.price::TEXT) OVER w AS previous_price,
+        c.negotiable::TEXT AS negotiable,
+        LAG(c.negotiable::TEXT) OVER w AS previous_negotiable,
+        c.title,
+        LAG(c.title) OVER w AS previous_title,
+        c.surface::TEXT AS surface,
+        LAG(c.surface::TEXT) OVER w AS previous_surface,
+        c.room_count::TEXT AS room_count,
+        LAG(c.room_count::TEXT) OVER w AS previous_room_count,
+        c.floor::TEXT AS floor,
+        LAG(c.floor::TEXT) OVER w AS previous_floor,
+        c.layout::TEXT AS layout,
+        LAG(c.layout::TEXT) OVER w AS previous_layout,
+        c.property_type::TEXT AS property_type,
+        LAG(c.property_type::TEXT) OVER w AS previous_property_type,
+        c.seller_name,
+        LAG(c.seller_name) OVER w AS previous_seller_name
+    FROM latest_classifieds c
+    JOIN sessions s USING (session)
+    WHERE ad_id_from_url(c.url) IS NOT NULL
+    WINDOW w AS (PARTITION BY ad_id_from_url(c.url) ORDER BY s.created_at)
+)
+SELECT
+    o.ad_id,
+    o.session,
+    o.previous_session,
+    o.url,
+    o.observed_at,
+    f.field,
+    f.old_value,
+    f.new_value
+FROM observations o
+CROSS JOIN LATERAL (
+    VALUES
+        ('price', o.previous_price, o.price),
+        ('negotiable', o.previous_negotiable, o.negotiable),
+        ('title', o.previous_title, o.title),
+        ('surface', o.previous_surface, o.surface),
+        ('room_count', o.previous_room_count, o.room_count),
+        ('floor', o.previous_floor, o.floor),
+        ('layout', o.previous_layout, o.layout),
+        ('property_type', o.previous_property_type, o.property_type),
+        ('seller_name', o.previous_seller_name, o.seller_name)
+) AS f (field, old_value, new_value)
+WHERE
+    o.previous_session IS NOT NULL
+    AND f.old_value IS DISTINCT FROM f.new_value;
+
+CREATE INDEX classified_changes_ad_id_idx
+    ON classified_changes (ad_id, observed_at);
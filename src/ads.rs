@@ -0,0 +1,246 @@
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{config::Config, util::PgTransaction};
+
+/// Prints the timeline of an ad across sessions: when it was first seen, what
+/// changed between observations and when it went away.
+#[derive(clap::Args)]
+pub struct HistoryCmd {
+    /// Canonical ad ID (e.g. olx:gC0Kq) or any URL of the ad.
+    pub ad: String,
+}
+
+impl HistoryCmd {
+    pub fn work(&self, config: &Config) -> anyhow::Result<()> {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("Failed building the Runtime")
+            .block_on(async move {
+                let pool = sqlx::postgres::PgPoolOptions::new()
+                    .connect(config.database_url.as_ref())
+                    .await
+                    .context("Failed to establish connection to postgres.")?;
+
+                let ad_id = resolve_ad_id(&pool, &self.ad).await?;
+                print_history(&pool, &ad_id).await
+            })
+    }
+}
+
+async fn resolve_ad_id(pool: &PgPool, ad: &str) -> anyhow::Result<String> {
+    if !ad.contains("://") {
+        return Ok(ad.to_string());
+    }
+
+    sqlx::query!(r#"SELECT ad_id_from_url($1) AS "ad_id""#, ad)
+        .fetch_one(pool)
+        .await
+        .context("Failed to resolve the ad ID.")?
+        .ad_id
+        .ok_or_else(|| anyhow::anyhow!("Cannot find an ad ID in {}", ad))
+}
+
+async fn print_history(pool: &PgPool, ad_id: &str) -> anyhow::Result<()> {
+    let ad = sqlx::query!(
+        r#"
+        SELECT
+          status::TEXT AS "status!",
+          url,
+          removed_at
+        FROM ads
+        WHERE ad_id=$1
+        "#,
+        ad_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to load the ad.")?;
+
+    let first = sqlx::query!(
+        r#"
+        SELECT
+          s.created_at AS "observed_at!",
+          c.session AS "session!",
+          c.price AS "price!",
+          c.currency::TEXT AS currency,
+          c.title AS "title!"
+        FROM latest_classifieds c
+        JOIN sessions s USING (session)
+        WHERE ad_id_from_url(c.url)=$1
+        ORDER BY s.created_at
+        LIMIT 1
+        "#,
+        ad_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to load the first observation.")?;
+
+    let changes = sqlx::query!(
+        r#"
+        SELECT
+          observed_at AS "observed_at!",
+          session AS "session!",
+          field AS "field!",
+          old_value,
+          new_value
+        FROM classified_changes
+        WHERE ad_id=$1
+        ORDER BY observed_at, field
+        "#,
+        ad_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to load the changes.")?;
+
+    match (&ad, &first) {
+        (None, None) => return Err(anyhow::anyhow!("No classified found for {}", ad_id)),
+        (Some(ad), _) => println!("{} | {} | {}", ad_id, ad.status, ad.url),
+        (None, Some(_)) => println!("{}", ad_id),
+    };
+
+    if let Some(first) = first {
+        println!(
+            "{} | {} | first seen | {} {} | {}",
+            first.observed_at,
+            first.session,
+            first.price,
+            first.currency.as_deref().unwrap_or("-"),
+            first.title
+        );
+    }
+
+    changes.iter().for_each(|change| {
+        println!(
+            "{} | {} | {} | {} -> {}",
+            change.observed_at,
+            change.session,
+            change.field,
+            change.old_value.as_deref().unwrap_or("-"),
+            change.new_value.as_deref().unwrap_or("-"),
+        );
+    });
+
+    if let Some(removed_at) = ad.and_then(|ad| ad.removed_at) {
+        println!("{} | removed", removed_at);
+    }
+
+    Ok(())
+}
+
+/// Diffs the ads discovered by a crawled session against what is known for
+/// its search. Discovered ads are active, or expired when their page is gone
+/// (404 or 410), other failures keep the status the ad had. Active ads of the
+/// search that were not discovered are removed, expired ones stay expired.
+/// Sessions without a search are left out.
+#[tracing::instrument(skip(transaction))]
+pub async fn update_ads<'a>(
+    transaction: &mut PgTransaction<'a>,
+    session: &Uuid,
+) -> sqlx::Result<()> {
+    let seen = sqlx::query!(
+        r#"
+        WITH discovered AS (
+          SELECT DISTINCT ON (ad_id_from_url(q.url))
+            ad_id_from_url(q.url) AS ad_id,
+            s.search,
+            q.url,
+            q.page_type,
+            CASE
+              WHEN q.failure_status IN (404, 410) THEN 'expired'
+              WHEN q.status='failed' THEN NULL
+              ELSE 'active'
+            END::ad_status AS status,
+            s.session,
+            s.created_at
+          FROM crawler_queue q
+          JOIN sessions s USING (session)
+          WHERE
+            q.session=$1
+            AND q.page_type <> 'olx_list'
+            AND s.search IS NOT NULL
+            AND ad_id_from_url(q.url) IS NOT NULL
+          ORDER BY ad_id_from_url(q.url), q.url
+        )
+        INSERT INTO ads
+        (
+          ad_id,
+          search,
+          url,
+          page_type,
+          status,
+          first_seen_session,
+          first_seen_at,
+          last_seen_session,
+          last_seen_at
+        )
+        SELECT
+          ad_id,
+          search,
+          url,
+          page_type,
+          COALESCE(status, 'active'),
+          session,
+          created_at,
+          session,
+          created_at
+        FROM discovered
+        ON CONFLICT (ad_id) DO UPDATE
+        SET
+          search=EXCLUDED.search,
+          url=EXCLUDED.url,
+          page_type=EXCLUDED.page_type,
+          status=COALESCE(
+            (SELECT d.status FROM discovered d WHERE d.ad_id=EXCLUDED.ad_id),
+            CASE WHEN ads.status='removed' THEN 'active' ELSE ads.status END
+          ),
+          last_seen_session=EXCLUDED.last_seen_session,
+          last_seen_at=EXCLUDED.last_seen_at,
+          removed_at=NULL
+        WHERE ads.last_seen_at <= EXCLUDED.last_seen_at
+        "#,
+        session,
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    let removed = sqlx::query!(
+        r#"
+        UPDATE ads
+        SET
+          status='removed',
+          removed_at=s.created_at
+        FROM sessions s
+        WHERE
+          s.session=$1
+          AND ads.search=s.search
+          AND ads.status='active'
+          AND ads.last_seen_at < s.created_at
+        "#,
+        session,
+    )
+    .execute(transaction)
+    .await?;
+
+    tracing::info!(
+        "Ads seen: {}, removed: {}.",
+        seen.rows_affected(),
+        removed.rows_affected()
+    );
+    Ok(())
+}
+
+/// Recomputes `classified_changes`, run whenever sessions get extracted. The
+/// view stays readable meanwhile, run it outside of the extraction's
+/// transaction so it sees the new classifieds.
+#[tracing::instrument(skip(pool))]
+pub async fn refresh_classified_changes(pool: &PgPool) -> sqlx::Result<()> {
+    sqlx::query!("REFRESH MATERIALIZED VIEW CONCURRENTLY classified_changes")
+        .execute(pool)
+        .await?;
+    Ok(())
+}
@@ -1,11 +1,191 @@
 use anyhow::Context;
+use chrono::Duration;
 use std::env::var;
 
+use crate::{page::PageType, util::parse_duration};
+
 pub const TEST_ASSETS_DIR: &str = "tests/crawler/assets";
 
 pub struct Config {
     pub database_url: url::Url,
     pub list_page_url: url::Url,
+    pub retry_policies: RetryPolicies,
+    pub queue_priorities: QueuePriorities,
+}
+
+/// How a crawl job is retried after a retryable error, read from
+/// `RETRY_POLICY_<PAGE TYPE>` as `<max attempts>,<backoff>[,<max backoff>]`
+/// (e.g. `RETRY_POLICY_OLX_LIST=5,1m,1h`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts before the job fails for good, the first one included.
+    pub max_attempts: usize,
+    /// Wait after the first failure, doubled after every other one.
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// The wait before the next attempt, none once `failed_attempts` used them all up.
+    pub fn next_attempt(&self, failed_attempts: usize) -> Option<Duration> {
+        if failed_attempts >= self.max_attempts {
+            return None;
+        }
+        let doublings = failed_attempts.saturating_sub(1).min(16) as u32;
+        Some((self.backoff * 2i32.pow(doublings)).min(self.max_backoff))
+    }
+
+    fn from_env(name: &str, default: Self) -> anyhow::Result<Self> {
+        match var(name) {
+            Ok(s) => s
+                .parse()
+                .with_context(|| format!("Failed to parse {} env var.", name)),
+            Err(_) => Ok(default),
+        }
+    }
+}
+
+impl std::str::FromStr for RetryPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split(',').map(str::trim).collect::<Vec<_>>();
+        let (max_attempts, backoff, max_backoff) = match parts[..] {
+            [max_attempts, backoff] => (max_attempts, backoff, None),
+            [max_attempts, backoff, max_backoff] => (max_attempts, backoff, Some(max_backoff)),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Expected <max attempts>,<backoff>[,<max backoff>]."
+                ))
+            }
+        };
+
+        Ok(Self {
+            max_attempts: max_attempts
+                .parse()
+                .context("Failed to parse the max attempts.")?,
+            backoff: parse_duration(backoff)?,
+            max_backoff: max_backoff
+                .map(parse_duration)
+                .transpose()?
+                .unwrap_or_else(|| Duration::hours(1)),
+        })
+    }
+}
+
+pub struct RetryPolicies {
+    pub olx_list: RetryPolicy,
+    pub olx_item: RetryPolicy,
+    pub storia_item: RetryPolicy,
+}
+
+impl RetryPolicies {
+    pub fn get(&self, page_type: PageType) -> &RetryPolicy {
+        match page_type {
+            PageType::OlxList => &self.olx_list,
+            PageType::OlxItem => &self.olx_item,
+            PageType::StoriaItem => &self.storia_item,
+        }
+    }
+
+    fn from_env() -> anyhow::Result<Self> {
+        // Losing a list page loses every item on it, worth waiting longer for.
+        let list = RetryPolicy {
+            max_attempts: 5,
+            backoff: Duration::minutes(1),
+            max_backoff: Duration::hours(1),
+        };
+        let item = RetryPolicy {
+            max_attempts: 3,
+            backoff: Duration::seconds(30),
+            max_backoff: Duration::minutes(10),
+        };
+
+        Ok(Self {
+            olx_list: RetryPolicy::from_env("RETRY_POLICY_OLX_LIST", list)?,
+            olx_item: RetryPolicy::from_env("RETRY_POLICY_OLX_ITEM", item)?,
+            storia_item: RetryPolicy::from_env("RETRY_POLICY_STORIA_ITEM", item)?,
+        })
+    }
+}
+
+/// Which jobs of a session are claimed first, read from `CRAWL_STRATEGY`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueueStrategy {
+    /// List pages first, queueing every item of the search early on.
+    DiscoverFirst,
+    /// Item pages first, keeping the queue short.
+    DrainFirst,
+}
+
+impl std::str::FromStr for QueueStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "discover-first" => Ok(Self::DiscoverFirst),
+            "drain-first" => Ok(Self::DrainFirst),
+            _ => Err(anyhow::anyhow!(
+                "Expected discover-first or drain-first, got {}.",
+                s
+            )),
+        }
+    }
+}
+
+/// The priority jobs are queued with per page type, lower ones are claimed
+/// first. Set by the strategy, each can be overridden with
+/// `CRAWL_PRIORITY_<PAGE TYPE>`. Queued jobs keep their priority.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueuePriorities {
+    pub olx_list: i16,
+    pub olx_item: i16,
+    pub storia_item: i16,
+}
+
+impl QueuePriorities {
+    pub fn get(&self, page_type: PageType) -> i16 {
+        match page_type {
+            PageType::OlxList => self.olx_list,
+            PageType::OlxItem => self.olx_item,
+            PageType::StoriaItem => self.storia_item,
+        }
+    }
+
+    pub fn of(strategy: QueueStrategy) -> Self {
+        let (list, item) = match strategy {
+            QueueStrategy::DiscoverFirst => (0, 1),
+            QueueStrategy::DrainFirst => (1, 0),
+        };
+        Self {
+            olx_list: list,
+            olx_item: item,
+            storia_item: item,
+        }
+    }
+
+    fn from_env() -> anyhow::Result<Self> {
+        let strategy = match var("CRAWL_STRATEGY") {
+            Ok(s) => s
+                .parse()
+                .context("Failed to parse CRAWL_STRATEGY env var.")?,
+            Err(_) => QueueStrategy::DiscoverFirst,
+        };
+        let defaults = Self::of(strategy);
+        let priority = |name: &str, default: i16| match var(name) {
+            Ok(s) => s
+                .trim()
+                .parse::<i16>()
+                .with_context(|| format!("Failed to parse {} env var.", name)),
+            Err(_) => Ok(default),
+        };
+
+        Ok(Self {
+            olx_list: priority("CRAWL_PRIORITY_OLX_LIST", defaults.olx_list)?,
+            olx_item: priority("CRAWL_PRIORITY_OLX_ITEM", defaults.olx_item)?,
+            storia_item: priority("CRAWL_PRIORITY_STORIA_ITEM", defaults.storia_item)?,
+        })
+    }
 }
 
 impl Config {
@@ -17,6 +197,44 @@ impl Config {
             list_page_url: var("LIST_PAGE_URL")
                 .context("LIST_PAGE missing, cannot parse")
                 .and_then(|s| url::Url::parse(&s).context("Failed to parse LIST_PAGE env var."))?,
+            retry_policies: RetryPolicies::from_env()?,
+            queue_priorities: QueuePriorities::from_env()?,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{QueuePriorities, QueueStrategy, RetryPolicy};
+    use crate::page::PageType;
+    use chrono::Duration;
+
+    #[test]
+    fn backs_off_exponentially() {
+        let policy: RetryPolicy = "4, 30s, 1m".parse().unwrap();
+
+        assert_eq!(policy.next_attempt(1), Some(Duration::seconds(30)));
+        assert_eq!(policy.next_attempt(2), Some(Duration::minutes(1)));
+        assert_eq!(policy.next_attempt(3), Some(Duration::minutes(1)));
+        assert_eq!(policy.next_attempt(4), None);
+        assert_eq!(
+            "3,10s".parse::<RetryPolicy>().unwrap().max_backoff,
+            Duration::hours(1)
+        );
+        assert!("3".parse::<RetryPolicy>().is_err());
+    }
+
+    #[test]
+    fn strategies_order_page_types() {
+        let discover = QueuePriorities::of("discover-first".parse().unwrap());
+        assert!(discover.get(PageType::OlxList) < discover.get(PageType::OlxItem));
+        assert_eq!(
+            discover.get(PageType::OlxItem),
+            discover.get(PageType::StoriaItem)
+        );
+
+        let drain = QueuePriorities::of(QueueStrategy::DrainFirst);
+        assert!(drain.get(PageType::StoriaItem) < drain.get(PageType::OlxList));
+        assert!("random".parse::<QueueStrategy>().is_err());
+    }
+}
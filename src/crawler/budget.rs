@@ -0,0 +1,214 @@
+use chrono::Duration;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::util::{parse_duration, PgTransaction};
+
+/// Limits of a crawl, checked by every worker before claiming a job. Once one
+/// is reached the crawl stops and the session is partially crawled, a later
+/// crawl without limits resumes it. Every limit counts from the start of the
+/// crawl, pages and bytes of earlier crawls of the session don't count.
+#[derive(clap::Args, Clone, Default, Debug, PartialEq, Eq)]
+pub struct CrawlBudget {
+    /// Stop once this crawl saved this many item pages.
+    #[arg(long)]
+    pub max_item_pages: Option<i64>,
+    /// Stop once this crawl downloaded this many bytes.
+    #[arg(long)]
+    pub max_bytes: Option<i64>,
+    /// Stop once this crawl ran this long, e.g. 30m.
+    #[arg(long, value_parser = parse_duration)]
+    pub max_duration: Option<Duration>,
+    /// Stop after this many fetches failed in a row, e.g. once blocked.
+    #[arg(long)]
+    pub max_consecutive_failures: Option<i64>,
+}
+
+/// What a crawl used of its budget so far.
+pub struct CrawlUsage {
+    pub item_pages: i64,
+    pub bytes: i64,
+    pub elapsed: Duration,
+    pub consecutive_failures: i64,
+}
+
+impl CrawlBudget {
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Which limit the usage reached, none while within budget.
+    pub fn exhausted(&self, usage: &CrawlUsage) -> Option<String> {
+        let reached = |max: Option<i64>, used: i64| max.filter(|max| used >= *max);
+
+        if let Some(max) = reached(self.max_item_pages, usage.item_pages) {
+            return Some(format!("Reached {} item pages.", max));
+        }
+        if let Some(max) = reached(self.max_bytes, usage.bytes) {
+            return Some(format!("Downloaded {} of {} bytes.", usage.bytes, max));
+        }
+        if let Some(max) = self.max_duration.filter(|max| usage.elapsed >= *max) {
+            return Some(format!("Ran for {}s.", max.num_seconds()));
+        }
+        if let Some(max) = reached(self.max_consecutive_failures, usage.consecutive_failures) {
+            return Some(format!("{} fetches failed in a row.", max));
+        }
+        None
+    }
+}
+
+/// Starts the budget of a new crawl of the session with fresh counters, or
+/// lifts it.
+pub async fn set_budget<'a>(
+    transaction: &mut PgTransaction<'a>,
+    session: &Uuid,
+    budget: &CrawlBudget,
+) -> sqlx::Result<()> {
+    if budget.is_unlimited() {
+        sqlx::query!("DELETE FROM crawl_budgets WHERE session=$1", session)
+            .execute(transaction)
+            .await?;
+        return Ok(());
+    }
+
+    sqlx::query!(
+        r#"
+        INSERT INTO crawl_budgets
+        (
+          session,
+          max_item_pages,
+          max_bytes,
+          max_duration_seconds,
+          max_consecutive_failures,
+          started_at
+        )
+        VALUES ($1, $2, $3, $4, $5, CURRENT_TIMESTAMP)
+        ON CONFLICT (session) DO UPDATE
+        SET
+          max_item_pages=EXCLUDED.max_item_pages,
+          max_bytes=EXCLUDED.max_bytes,
+          max_duration_seconds=EXCLUDED.max_duration_seconds,
+          max_consecutive_failures=EXCLUDED.max_consecutive_failures,
+          started_at=EXCLUDED.started_at,
+          item_pages=0,
+          downloaded_bytes=0,
+          consecutive_failures=0
+        "#,
+        session,
+        budget.max_item_pages,
+        budget.max_bytes,
+        budget.max_duration.map(|d| d.num_seconds()),
+        budget.max_consecutive_failures,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// The limit of the session's crawl that was reached, if any.
+pub async fn exhausted_budget(pool: &PgPool, session: &Uuid) -> sqlx::Result<Option<String>> {
+    let b = match sqlx::query!(
+        r#"
+        SELECT
+          max_item_pages,
+          max_bytes,
+          max_duration_seconds,
+          max_consecutive_failures,
+          item_pages,
+          downloaded_bytes,
+          consecutive_failures,
+          EXTRACT(EPOCH FROM CURRENT_TIMESTAMP - started_at)::BIGINT AS "elapsed_seconds!"
+        FROM crawl_budgets
+        WHERE session=$1
+        "#,
+        session,
+    )
+    .fetch_optional(pool)
+    .await?
+    {
+        Some(b) => b,
+        None => return Ok(None),
+    };
+
+    let budget = CrawlBudget {
+        max_item_pages: b.max_item_pages,
+        max_bytes: b.max_bytes,
+        max_duration: b.max_duration_seconds.map(Duration::seconds),
+        max_consecutive_failures: b.max_consecutive_failures,
+    };
+    Ok(budget.exhausted(&CrawlUsage {
+        item_pages: b.item_pages,
+        bytes: b.downloaded_bytes,
+        elapsed: Duration::seconds(b.elapsed_seconds),
+        consecutive_failures: b.consecutive_failures,
+    }))
+}
+
+/// Counts a settled fetch against the session's budget, if it has one.
+pub async fn record_usage<'a>(
+    transaction: &mut PgTransaction<'a>,
+    session: &Uuid,
+    item_page: bool,
+    bytes: i64,
+    failed: bool,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE crawl_budgets
+        SET
+          item_pages=item_pages + CASE WHEN $2 THEN 1 ELSE 0 END,
+          downloaded_bytes=downloaded_bytes + $3,
+          consecutive_failures=CASE WHEN $4 THEN consecutive_failures + 1 ELSE 0 END
+        WHERE session=$1
+        "#,
+        session,
+        item_page,
+        bytes,
+        failed,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CrawlBudget, CrawlUsage};
+    use chrono::Duration;
+
+    #[test]
+    fn stops_on_the_first_limit_reached() {
+        let usage = CrawlUsage {
+            item_pages: 10,
+            bytes: 2048,
+            elapsed: Duration::minutes(5),
+            consecutive_failures: 2,
+        };
+
+        assert!(CrawlBudget::default().is_unlimited());
+        assert_eq!(CrawlBudget::default().exhausted(&usage), None);
+
+        let within = CrawlBudget {
+            max_item_pages: Some(11),
+            max_bytes: Some(4096),
+            max_duration: Some(Duration::minutes(10)),
+            max_consecutive_failures: Some(3),
+        };
+        assert!(!within.is_unlimited());
+        assert_eq!(within.exhausted(&usage), None);
+
+        let failing = CrawlBudget {
+            max_consecutive_failures: Some(2),
+            ..within.clone()
+        };
+        assert_eq!(
+            failing.exhausted(&usage).as_deref(),
+            Some("2 fetches failed in a row.")
+        );
+        let slow = CrawlBudget {
+            max_duration: Some(Duration::minutes(5)),
+            ..within
+        };
+        assert_eq!(slow.exhausted(&usage).as_deref(), Some("Ran for 300s."));
+    }
+}
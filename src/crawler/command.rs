@@ -1,17 +1,40 @@
 use anyhow::Context;
 use uuid::Uuid;
 
-use crate::{config::Config, util::try_parse_session};
+use crate::{
+    config::Config,
+    util::{shutdown_signal, try_parse_session},
+};
 
-use super::{crawl, CrawlOptions};
+use super::{
+    budget::CrawlBudget, crawl, join::CrawlJoinCmd, requeue::CrawlRequeueCmd, CrawlOptions,
+};
 
+/// Crawls a new session, or resumes the given one.
 #[derive(clap::Args)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct CrawlCmd {
+    #[command(subcommand)]
+    command: Option<CrawlCommands>,
     pub session: Option<String>,
+    #[command(flatten)]
+    pub budget: CrawlBudget,
+}
+
+#[derive(clap::Subcommand)]
+enum CrawlCommands {
+    Requeue(CrawlRequeueCmd),
+    Join(CrawlJoinCmd),
 }
 
 impl CrawlCmd {
     pub fn work(&self, config: &Config) -> anyhow::Result<()> {
+        match &self.command {
+            Some(CrawlCommands::Requeue(cmd)) => return cmd.work(config),
+            Some(CrawlCommands::Join(cmd)) => return cmd.work(config),
+            None => {}
+        };
+
         tokio::runtime::Builder::new_multi_thread()
             .enable_all()
             .build()
@@ -24,8 +47,10 @@ impl CrawlCmd {
                     None => None,
                 };
                 let options = CrawlOptions {
+                    budget: self.budget.clone(),
                     session,
                     config,
+                    shutdown: shutdown_signal()?,
                     pool: sqlx::postgres::PgPoolOptions::new()
                         .acquire_timeout(std::time::Duration::from_secs(2))
                         .connect_lazy(config.database_url.as_ref())
@@ -1,19 +1,24 @@
 use crate::{
-    crawler::page::{get_list_next_page_url, get_list_urls, get_page, save_page},
-    page::{PageType, SavedPage},
-    util::PgTransaction,
+    config::{QueuePriorities, RetryPolicies, RetryPolicy},
+    crawler::{
+        budget::{exhausted_budget, record_usage},
+        page::{get_list_next_page_url, get_list_urls, get_page, save_page, validate_page},
+        worker::{deregister_worker, heartbeat, register_worker, LEASE},
+    },
+    page::{PageType, PageUrl, SavedPage},
+    util::{PgTransaction, Shutdown},
 };
 use anyhow::Context;
-use sqlx::PgPool;
+use sqlx::{postgres::PgListener, PgPool};
+use std::sync::Arc;
+use tokio::sync::Notify;
 
-const MAX_RETRIES: usize = 3;
-
-#[derive(sqlx::Type, Copy, Clone)]
+#[derive(sqlx::Type, clap::ValueEnum, Copy, Clone, PartialEq, Eq, Debug)]
 #[sqlx(type_name = "crawl_status", rename_all = "snake_case")]
 pub enum CrawlStatus {
     New,
     Retrying,
-    Success,
+    Completed,
     Failed,
 }
 
@@ -31,85 +36,321 @@ impl std::fmt::Display for RetrievedCrawlJob {
     }
 }
 
-#[tracing::instrument(skip(pool))]
-pub async fn process_jobs(pool: &PgPool, session: &uuid::Uuid) -> anyhow::Result<()> {
+pub enum JobsOutcome {
+    /// No job left to run.
+    Done,
+    /// Stopped by the named signal, the remaining jobs stay queued.
+    Interrupted(&'static str),
+    /// Stopped on the crawl budget limit it names, the remaining jobs stay
+    /// queued.
+    Exhausted(String),
+}
+
+/// Postgres channel notified with the session UUID every time jobs are queued
+/// or settled.
+pub const JOBS_CHANNEL: &str = "crawler_jobs";
+
+/// Longest sleep between two looks at the queue, in case a notification got lost.
+const MAX_IDLE_WAIT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Pause after a database error, before trying again.
+const ERROR_PAUSE: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Runs the due jobs of the session until none is left, alongside any other
+/// worker of the session. A job moves from new to completed, or to retrying
+/// after a retryable error until its page type's retry policy gives up and
+/// it's failed, fatal errors fail it right away. While only deferred jobs, or
+/// jobs claimed by other workers, are left it sleeps until the earliest could
+/// be claimed, or until jobs change. Due jobs are claimed by priority, then
+/// in the order they came due, queued jobs get their page type's priority.
+/// Failing to reach the database pauses the worker instead of ending it.
+#[tracing::instrument(skip(pool, policies, priorities, shutdown))]
+pub async fn process_jobs(
+    pool: &PgPool,
+    session: &uuid::Uuid,
+    policies: &RetryPolicies,
+    priorities: &QueuePriorities,
+    shutdown: Shutdown,
+) -> anyhow::Result<JobsOutcome> {
+    let worker = register_worker(pool, session)
+        .await
+        .context("Failed to register the worker.")?;
+    let heartbeat = tokio::spawn(heartbeat(pool.clone(), worker, *session));
+
+    let jobs_changed = Arc::new(Notify::new());
+    let listener = match jobs_listener(pool).await {
+        Ok(listener) => Some(tokio::spawn(listen_for_jobs(
+            listener,
+            *session,
+            jobs_changed.clone(),
+        ))),
+        Err(e) => {
+            tracing::warn!(
+                "Failed to listen for queued jobs, polling instead ({:?})",
+                e
+            );
+            None
+        }
+    };
+
+    let outcome = run_jobs(
+        pool,
+        session,
+        &worker,
+        policies,
+        priorities,
+        &jobs_changed,
+        shutdown,
+    )
+    .await;
+
+    if let Some(listener) = listener {
+        listener.abort();
+    }
+    heartbeat.abort();
+    if let Err(e) = deregister_worker(pool, &worker).await {
+        tracing::error!("Failed to deregister worker {} ({:?})", worker, e);
+    }
+    outcome
+}
+
+async fn jobs_listener(pool: &PgPool) -> sqlx::Result<PgListener> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(JOBS_CHANNEL).await?;
+    Ok(listener)
+}
+
+/// Wakes the scheduler up whenever jobs of `session` change, a single permit
+/// is kept so changes while it's busy aren't missed.
+async fn listen_for_jobs(mut listener: PgListener, session: uuid::Uuid, jobs_changed: Arc<Notify>) {
+    let session = session.to_string();
     loop {
-        if let Ok(mut transaction) = pool.begin().await {
-            let job_result = sqlx::query_as!(
-                RetrievedCrawlJob,
-                r#"
-                SELECT
-                  session,
-                  url,
-                  page_type as "page_type: _",
-                  retries
-                FROM crawler_queue
-                WHERE
-                  status IN ('new', 'retrying')
-                  AND session=$1
-                FOR UPDATE
-                SKIP LOCKED
-                LIMIT 1
-                "#,
-                session
-            )
-            .fetch_optional(&mut transaction)
-            .await;
-            match job_result {
-                Ok(Some(job)) => match process_job(&mut transaction, &job).await {
-                    Ok(_) => {
-                        transaction.commit().await.ok();
+        match listener.recv().await {
+            Ok(notification) if notification.payload() == session => jobs_changed.notify_one(),
+            Ok(_) => {}
+            Err(e) => {
+                tracing::error!("Jobs listener failed, falling back to polling ({:?})", e);
+                return;
+            }
+        }
+    }
+}
+
+async fn run_jobs(
+    pool: &PgPool,
+    session: &uuid::Uuid,
+    worker: &uuid::Uuid,
+    policies: &RetryPolicies,
+    priorities: &QueuePriorities,
+    jobs_changed: &Notify,
+    mut shutdown: Shutdown,
+) -> anyhow::Result<JobsOutcome> {
+    loop {
+        if let Some(signal) = *shutdown.borrow() {
+            tracing::info!("Stopped claiming jobs ({}).", signal);
+            return Ok(JobsOutcome::Interrupted(signal));
+        }
+        match exhausted_budget(pool, session).await {
+            Ok(Some(reason)) => {
+                tracing::warn!("Stopped claiming jobs, {}", reason);
+                return Ok(JobsOutcome::Exhausted(reason));
+            }
+            Ok(None) => {}
+            Err(e) => {
+                tracing::error!("Failed to check the crawl budget {:?}", e);
+                tokio::time::sleep(ERROR_PAUSE).await;
+                continue;
+            }
+        };
+        let job = match claim_job(pool, session, worker).await {
+            Ok(Some(job)) => job,
+            Ok(None) => {
+                tracing::info!("No more immediate jobs in queue");
+                match next_job_due_at(pool, session).await {
+                    Ok(Some(not_before)) => {
+                        let wait = (not_before - chrono::Utc::now())
+                            .to_std()
+                            .unwrap_or_default()
+                            .min(MAX_IDLE_WAIT);
+                        tracing::info!("Next job in {} seconds, sleeping...", wait.as_secs());
+                        tokio::select! {
+                            _ = tokio::time::sleep(wait) => {}
+                            _ = jobs_changed.notified() => tracing::info!("Jobs changed, waking up."),
+                            Ok(()) = shutdown.changed() => {}
+                        };
                     }
-                    Err(e) => {
-                        tracing::error!("Failed to process job {:?}", e);
-                        return Err(e);
+                    Ok(None) => {
+                        tracing::info!("No defered jobs in queue. Completing session.");
+                        return Ok(JobsOutcome::Done);
                     }
-                },
-                Ok(None) => {
-                    tracing::info!("No more immediate jobs in queue");
-                    transaction.rollback().await.ok();
-                    match sqlx::query!(
-                        r#"
-                        SELECT
-                          not_before
-                        FROM crawler_queue
-                        WHERE
-                          status IN ('new', 'retrying')
-                          AND session=$1
-                        LIMIT 1
-                        "#,
-                        session
-                    )
-                    .fetch_optional(pool)
-                    .await
-                    {
-                        Ok(Some(result)) => {
-                            let utc: chrono::DateTime<chrono::Utc> = chrono::Utc::now();
-                            let timeout = result.not_before.signed_duration_since(utc);
-                            tracing::info!(
-                                "Next job in {} seconds, sleeping...",
-                                timeout.num_seconds()
-                            );
-
-                            std::thread::sleep(timeout.to_std().unwrap());
-                        }
-                        Ok(None) => {
-                            tracing::info!("No defered jobs in queue. Completing session.");
-                            return Ok(());
-                        }
-                        Err(e) => {
-                            tracing::error!("Failed to check for defered job {:?}", e);
-                        }
+                    Err(e) => {
+                        tracing::error!("Failed to check for defered job {:?}", e);
+                        tokio::time::sleep(ERROR_PAUSE).await;
                     }
                 }
-                Err(e) => {
-                    tracing::error!("Failed to fetch next job {:?}", e);
+                continue;
+            }
+            Err(e) => {
+                tracing::error!("Failed to claim next job {:?}", e);
+                tokio::time::sleep(ERROR_PAUSE).await;
+                continue;
+            }
+        };
+
+        let policy = policies.get(job.page_type);
+        let fetched = tokio::select! {
+            fetched = fetch_job(&job) => fetched,
+            Ok(()) = shutdown.changed() => {
+                // Given back when the worker deregisters.
+                tracing::warn!("Interrupted job {}.", job);
+                continue;
+            }
+        };
+
+        let mut transaction = match begin(pool, &mut shutdown).await {
+            Some(transaction) => transaction,
+            None => {
+                tracing::warn!("Interrupted job {}.", job);
+                continue;
+            }
+        };
+        match settle_job(&mut transaction, &job, worker, policy, priorities, fetched).await {
+            Ok(true) => transaction.commit().await?,
+            Ok(false) => {
+                tracing::warn!("Lost the claim on {}, dropping it.", job);
+                transaction.rollback().await?;
+            }
+            Err(e) => {
+                // A failed statement leaves the transaction unusable, the
+                // attempt is recorded in a new one.
+                tracing::error!("Failed to process job {:?}", e);
+                transaction.rollback().await.ok();
+                let mut transaction = match begin(pool, &mut shutdown).await {
+                    Some(transaction) => transaction,
+                    None => continue,
+                };
+                if holds_claim(&mut transaction, &job, worker).await? {
+                    retry_or_fail(&mut transaction, &job, policy, &e)
+                        .await
+                        .context("Failed to record the job failure.")?;
                 }
-            };
+                transaction.commit().await?;
+            }
+        };
+    }
+}
+
+/// Begins a transaction, pausing after each failure to connect like the
+/// other database errors of the loop. None once shut down, the claimed job is
+/// then given back when the worker deregisters.
+async fn begin(pool: &PgPool, shutdown: &mut Shutdown) -> Option<PgTransaction<'static>> {
+    loop {
+        if shutdown.borrow().is_some() {
+            return None;
+        }
+        match pool.begin().await {
+            Ok(transaction) => return Some(transaction),
+            Err(e) => {
+                tracing::error!("Failed to begin a transaction {:?}", e);
+                tokio::select! {
+                    _ = tokio::time::sleep(ERROR_PAUSE) => {}
+                    Ok(()) = shutdown.changed() => {}
+                };
+            }
         }
     }
 }
 
+/// Leases the next due job that nobody holds, or whose lease ran out.
+async fn claim_job(
+    pool: &PgPool,
+    session: &uuid::Uuid,
+    worker: &uuid::Uuid,
+) -> sqlx::Result<Option<RetrievedCrawlJob>> {
+    sqlx::query_as!(
+        RetrievedCrawlJob,
+        r#"
+        UPDATE crawler_queue q
+        SET
+          claimed_by=$2,
+          lease_until=CURRENT_TIMESTAMP + make_interval(secs => $3)
+        FROM (
+          SELECT
+            session,
+            url
+          FROM crawler_queue
+          WHERE
+            status IN ('new', 'retrying')
+            AND session=$1
+            AND not_before <= CURRENT_TIMESTAMP
+            AND (claimed_by IS NULL OR lease_until < CURRENT_TIMESTAMP)
+          ORDER BY priority, not_before, added_at
+          FOR UPDATE
+          SKIP LOCKED
+          LIMIT 1
+        ) claimable
+        WHERE q.session=claimable.session AND q.url=claimable.url
+        RETURNING
+          q.session,
+          q.url,
+          q.page_type as "page_type: _",
+          q.retries
+        "#,
+        session,
+        worker,
+        LEASE.as_secs_f64(),
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Locks the job if the worker still holds it, another worker may have
+/// claimed it after the lease ran out.
+async fn holds_claim<'a>(
+    transaction: &mut PgTransaction<'a>,
+    job: &RetrievedCrawlJob,
+    worker: &uuid::Uuid,
+) -> sqlx::Result<bool> {
+    Ok(sqlx::query!(
+        r#"
+        SELECT 1 AS "claimed"
+        FROM crawler_queue
+        WHERE
+          session=$1
+          AND url=$2
+          AND claimed_by=$3
+        FOR UPDATE
+        "#,
+        &job.session,
+        &job.url,
+        worker,
+    )
+    .fetch_optional(transaction)
+    .await?
+    .is_some())
+}
+
+/// When the earliest pending job of the session can be claimed, none when
+/// the queue is drained.
+async fn next_job_due_at(
+    pool: &PgPool,
+    session: &uuid::Uuid,
+) -> sqlx::Result<Option<chrono::DateTime<chrono::Utc>>> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT
+          MIN(GREATEST(not_before, lease_until))
+        FROM crawler_queue
+        WHERE
+          status IN ('new', 'retrying')
+          AND session=$1
+        "#,
+        session
+    )
+    .fetch_one(pool)
+    .await
+}
+
 #[tracing::instrument(skip_all)]
 async fn mark_failed<'a>(
     transaction: &mut PgTransaction<'a>,
@@ -121,75 +362,132 @@ async fn mark_failed<'a>(
         UPDATE crawler_queue
         SET
             status='failed',
-            failure_error=$1
+            failure_error=$1,
+            failure_status=$4,
+            claimed_by=NULL,
+            lease_until=NULL
         WHERE session=$2
         AND url=$3
         "#,
-        e.to_string(),
+        format!("{:#}", e),
         &job.session,
         &job.url,
+        http_status(e).map(|status| status.as_u16() as i16),
     )
     .execute(transaction)
     .await?;
     Ok(())
 }
 
+/// Schedules another attempt once the backoff is over, or fails the job when
+/// the policy has no attempts left.
+#[tracing::instrument(skip_all)]
+async fn retry_or_fail<'a>(
+    transaction: &mut PgTransaction<'a>,
+    job: &RetrievedCrawlJob,
+    policy: &RetryPolicy,
+    e: &anyhow::Error,
+) -> Result<(), sqlx::Error> {
+    let failed_attempts = job.retries.len() + 1;
+    let backoff = match policy.next_attempt(failed_attempts) {
+        Some(backoff) => backoff,
+        None => {
+            tracing::info!(
+                "No attempts left ({}), marking as failed with error: {:?}",
+                failed_attempts,
+                e
+            );
+            return mark_failed(transaction, job, e).await;
+        }
+    };
+
+    tracing::warn!(
+        "Failed with retryable error: {:?}, retrying in {}s (attempt {}/{}).",
+        e,
+        backoff.num_seconds(),
+        failed_attempts + 1,
+        policy.max_attempts
+    );
+    // The database clock, workers on other hosts compare against it.
+    sqlx::query!(
+        r#"
+        UPDATE crawler_queue
+        SET
+          status='retrying',
+          retries=array_append(retries, $3),
+          not_before=CURRENT_TIMESTAMP + make_interval(secs => $4),
+          claimed_by=NULL,
+          lease_until=NULL
+        WHERE session=$1
+        AND url=$2
+        "#,
+        &job.session,
+        &job.url,
+        format!("{:#}", e),
+        backoff.num_milliseconds() as f64 / 1000.0,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Saves what the job fetched, or records why it failed, if the worker still
+/// holds the job. Returns whether it did.
 #[tracing::instrument(skip_all, fields(job = %job))]
-async fn process_job<'a>(
+async fn settle_job<'a>(
     transaction: &mut PgTransaction<'a>,
     job: &RetrievedCrawlJob,
-) -> anyhow::Result<()> {
-    match run_job(transaction, job).await {
-        Ok(_) => {
+    worker: &uuid::Uuid,
+    policy: &RetryPolicy,
+    priorities: &QueuePriorities,
+    fetched: Result<Fetched, ProcessedJobError>,
+) -> anyhow::Result<bool> {
+    if !holds_claim(transaction, job, worker).await? {
+        return Ok(false);
+    }
+
+    let (item_page, bytes, failed) = match &fetched {
+        Ok(fetched) => (matches!(fetched, Fetched::Item(_)), fetched.bytes(), false),
+        Err(_) => (false, 0, true),
+    };
+    record_usage(transaction, &job.session, item_page, bytes as i64, failed)
+        .await
+        .context("Failed to record the crawl budget usage.")?;
+
+    match fetched {
+        Ok(fetched) => {
+            save_job(transaction, job, priorities, fetched).await?;
             sqlx::query!(
                 r#"
                 UPDATE crawler_queue
-                SET status='completed'
+                SET
+                  status='completed',
+                  claimed_by=NULL,
+                  lease_until=NULL
                 WHERE session=$1
                 AND url=$2
                 "#,
                 &job.session,
                 &job.url
             )
-            .execute(transaction)
+            .execute(&mut *transaction)
             .await
             .context("Failed to update crawled job status")?;
         }
         Err(ProcessedJobError::RetryableError(e)) => {
-            let current_retries = job.retries.len() + 1;
-            if current_retries >= MAX_RETRIES {
-                tracing::info!(
-                    "Exhausted MAX_RETRIES, marking as failed with error: {:?}",
-                    e
-                );
-                mark_failed(transaction, job, &e).await?;
-            } else {
-                tracing::error!("Failed with retryable error: {:?}.", e);
-                tracing::info!("Re-queueing job (count: {}).", current_retries);
-                sqlx::query!(
-                    r#"
-                    UPDATE crawler_queue
-                    SET
-                      status='failed',
-                      retries=array_append(retries, $3),
-                      not_before=CURRENT_TIMESTAMP
-                    WHERE session=$1
-                    AND url=$2
-                    "#,
-                    &job.session,
-                    &job.url,
-                    e.to_string(),
-                )
-                .execute(transaction)
-                .await?;
-            }
+            retry_or_fail(transaction, job, policy, &e).await?;
         }
         Err(ProcessedJobError::FatalError(e)) => {
             tracing::error!("Failed with fatal error: {:?}.", e);
             mark_failed(transaction, job, &e).await?;
         }
     };
-    Ok(())
+
+    // Idle workers may be waiting for this one.
+    notify_jobs(transaction, &job.session)
+        .await
+        .context("Failed to notify the settled job.")?;
+    Ok(true)
 }
 
 enum ProcessedJobError {
@@ -197,71 +495,120 @@ enum ProcessedJobError {
     FatalError(anyhow::Error),
 }
 
-#[tracing::instrument(skip_all)]
-async fn run_job<'a>(
-    transaction: &mut PgTransaction<'a>,
-    job: &RetrievedCrawlJob,
-) -> Result<(), ProcessedJobError> {
+/// The HTTP status the page was answered with, when that's what failed.
+fn http_status(e: &anyhow::Error) -> Option<reqwest::StatusCode> {
+    e.chain()
+        .find_map(|cause| cause.downcast_ref::<reqwest::Error>())
+        .and_then(reqwest::Error::status)
+}
+
+/// Pages that are gone don't come back, anything else may be a hiccup.
+fn fetch_error(e: anyhow::Error) -> ProcessedJobError {
+    match http_status(&e) {
+        Some(reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::GONE) => {
+            ProcessedJobError::FatalError(e)
+        }
+        _ => ProcessedJobError::RetryableError(e),
+    }
+}
+
+/// What a job fetched, saved once the job turns out to be still claimed.
+enum Fetched {
+    Item(String),
+    List {
+        next_page_url: Option<url::Url>,
+        pages_urls: Vec<PageUrl>,
+        bytes: usize,
+    },
+}
+
+impl Fetched {
+    fn bytes(&self) -> usize {
+        match self {
+            Self::Item(content) => content.len(),
+            Self::List { bytes, .. } => *bytes,
+        }
+    }
+}
+
+/// Fetches the page of the job, outside of any transaction.
+#[tracing::instrument(skip_all, fields(job = %job))]
+async fn fetch_job(job: &RetrievedCrawlJob) -> Result<Fetched, ProcessedJobError> {
     let url = url::Url::parse(&job.url)
         .context("Failed to parse error.")
         .map_err(ProcessedJobError::FatalError)?;
-    match job.page_type {
-        PageType::OlxItem => {
-            tracing::info!("saving olx item page: {}", &url);
-            let page = SavedPage {
-                session: &job.session,
-                url: url.to_string(),
-                page_type: job.page_type,
-                crawled_at: chrono::Utc::now(),
-                content: get_page(&url)
-                    .await
-                    .map_err(ProcessedJobError::RetryableError)?,
-            };
 
-            save_page(transaction, &page)
-                .await
-                .context("Failed to save page")
-                .map_err(ProcessedJobError::RetryableError)?;
+    tracing::info!("fetching {}", job);
+    // Finished well within the lease, or someone else may take the job over.
+    let content = tokio::time::timeout(LEASE / 2, get_page(&url))
+        .await
+        .map_err(|_| {
+            ProcessedJobError::RetryableError(anyhow::anyhow!("Timed out fetching page."))
+        })?
+        .map_err(fetch_error)?;
+    validate_page(job.page_type, &content).map_err(ProcessedJobError::RetryableError)?;
+
+    match job.page_type {
+        PageType::OlxItem | PageType::StoriaItem => Ok(Fetched::Item(content)),
+        PageType::OlxList => {
+            let document = scraper::Html::parse_document(&content);
+            Ok(Fetched::List {
+                next_page_url: get_list_next_page_url(&document),
+                pages_urls: get_list_urls(&document).map_err(ProcessedJobError::FatalError)?,
+                bytes: content.len(),
+            })
         }
-        PageType::StoriaItem => {
-            tracing::info!("saving storia item page: {}", &url);
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn save_job<'a>(
+    transaction: &mut PgTransaction<'a>,
+    job: &RetrievedCrawlJob,
+    priorities: &QueuePriorities,
+    fetched: Fetched,
+) -> anyhow::Result<()> {
+    match fetched {
+        Fetched::Item(content) => {
+            tracing::info!("saving item page: {}", job);
             let page = SavedPage {
                 session: &job.session,
-                url: url.to_string(),
+                url: job.url.clone(),
                 page_type: job.page_type,
                 crawled_at: chrono::Utc::now(),
-                content: get_page(&url)
-                    .await
-                    .map_err(ProcessedJobError::RetryableError)?,
+                content,
             };
             save_page(transaction, &page)
                 .await
-                .context("Failed to save page")
-                .map_err(ProcessedJobError::RetryableError)?;
+                .context("Failed to save page")?;
         }
-        PageType::OlxList => {
-            tracing::info!("saving olx list page: {}", &url);
-            let content = get_page(&url)
-                .await
-                .map_err(ProcessedJobError::RetryableError)?;
-            let document = scraper::Html::parse_document(&content);
-            if let Some(url) = get_list_next_page_url(&document) {
+        Fetched::List {
+            next_page_url,
+            pages_urls,
+            ..
+        } => {
+            if let Some(url) = next_page_url {
                 tracing::info!("Found next page url");
-                insert_job(transaction, &job.session, &url, PageType::OlxList)
-                    .await
-                    .map_err(ProcessedJobError::RetryableError)?;
+                insert_job(
+                    transaction,
+                    &job.session,
+                    &url,
+                    PageType::OlxList,
+                    priorities.get(PageType::OlxList),
+                )
+                .await?;
             }
-            let pages_urls = get_list_urls(&document).map_err(ProcessedJobError::FatalError)?;
             tracing::info!("Found {} item urls", pages_urls.len());
             for page_url in pages_urls {
+                let page_type = PageType::from(&page_url);
                 insert_job(
                     transaction,
                     &job.session,
                     page_url.as_ref(),
-                    PageType::from(&page_url),
+                    page_type,
+                    priorities.get(page_type),
                 )
-                .await
-                .map_err(ProcessedJobError::RetryableError)?;
+                .await?;
             }
         }
     };
@@ -274,6 +621,7 @@ pub async fn insert_job<'a>(
     session: &uuid::Uuid,
     url: &url::Url,
     page_type: PageType,
+    priority: i16,
 ) -> anyhow::Result<()> {
     sqlx::query!(
         r#"
@@ -281,19 +629,39 @@ pub async fn insert_job<'a>(
             session,
             url,
             page_type,
+            priority,
             added_at,
             not_before
         ) VALUES (
-            $1, $2, $3, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP
+            $1, $2, $3, $4, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP
         )
         ON CONFLICT (session, url) DO NOTHING
         "#,
         session,
         url.as_str(),
         page_type as PageType,
+        priority,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to insert into cralwer_queue.")?;
+
+    notify_jobs(transaction, session)
+        .await
+        .context("Failed to notify the queued job.")
+}
+
+/// Wakes up the idle workers of `session` once the transaction commits.
+pub async fn notify_jobs<'a>(
+    transaction: &mut PgTransaction<'a>,
+    session: &uuid::Uuid,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        "SELECT pg_notify($1, $2)",
+        JOBS_CHANNEL,
+        session.to_string()
     )
     .execute(transaction)
     .await
-    .context("Failed to insert into cralwer_queue.")
     .map(|_| ())
 }
@@ -0,0 +1,69 @@
+use anyhow::Context;
+
+use crate::{
+    config::Config,
+    session::SessionStatus,
+    util::{shutdown_signal, try_parse_session},
+};
+
+use super::job::{process_jobs, JobsOutcome};
+
+/// Helps drain the queue of a session being crawled, e.g. from another host.
+/// The crawl that started the session still completes it.
+#[derive(clap::Args)]
+pub struct CrawlJoinCmd {
+    pub session: String,
+}
+
+impl CrawlJoinCmd {
+    pub fn work(&self, config: &Config) -> anyhow::Result<()> {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("Failed building the Runtime")
+            .block_on(async move {
+                tracing_appender::rolling::never("logs", "crawler.log");
+                let session = try_parse_session(&self.session)?;
+                let pool = sqlx::postgres::PgPoolOptions::new()
+                    .acquire_timeout(std::time::Duration::from_secs(2))
+                    .connect_lazy(config.database_url.as_ref())
+                    .context("Failed to establish lazy connection to postgres.")?;
+
+                let status = sqlx::query_scalar!(
+                    r#"SELECT status AS "status: SessionStatus" FROM sessions WHERE session=$1"#,
+                    session,
+                )
+                .fetch_optional(&pool)
+                .await
+                .context("Failed retrieving session.")?
+                .ok_or_else(|| anyhow::anyhow!("No session found in DB."))?;
+                if status != SessionStatus::Crawling {
+                    return Err(anyhow::anyhow!(
+                        "Session {} is {}, only crawling sessions can be joined.",
+                        session,
+                        status
+                    ));
+                }
+
+                match process_jobs(
+                    &pool,
+                    &session,
+                    &config.retry_policies,
+                    &config.queue_priorities,
+                    shutdown_signal()?,
+                )
+                .await?
+                {
+                    JobsOutcome::Done => println!("Queue of session {} drained.", session),
+                    JobsOutcome::Interrupted(signal) => {
+                        println!("Left session {} ({}).", session, signal)
+                    }
+                    JobsOutcome::Exhausted(reason) => {
+                        println!("Budget of session {} exhausted: {}", session, reason)
+                    }
+                };
+
+                Ok(())
+            })
+    }
+}
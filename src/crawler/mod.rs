@@ -1,97 +1,223 @@
+pub mod budget;
 pub mod command;
 pub mod job;
+pub mod join;
 pub mod page;
+pub mod requeue;
+pub mod worker;
 
 use anyhow::Context;
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::{config::Config, page::PageType};
+use crate::{
+    ads::update_ads,
+    config::{Config, QueuePriorities},
+    page::PageType,
+    session::{stats::QueueCount, transition, SessionStatus},
+    util::Shutdown,
+};
 
-use self::job::{insert_job, process_jobs};
+use self::{
+    budget::{set_budget, CrawlBudget},
+    job::{insert_job, process_jobs, JobsOutcome},
+};
 
 pub struct CrawlOptions<'a> {
+    pub budget: CrawlBudget,
     pub config: &'a Config,
     pub pool: PgPool,
     pub session: Option<uuid::Uuid>,
+    pub shutdown: Shutdown,
 }
 
 pub async fn crawl<'a>(options: &'a CrawlOptions<'a>) -> anyhow::Result<()> {
     let session = match options.session {
         Some(session) => {
             tracing::info!("Reusing session {}", session);
+            print_resume_summary(&options.pool, &session).await?;
 
-            match sqlx::query!(
-                r#"
-                SELECT
-                    session,
-                    crawled_at
-                FROM sessions
-                WHERE session=$1
-                "#,
-                session,
-            )
-            .fetch_optional(&options.pool)
-            .await
-            .context("Failed retrieving existing session.")?
-            {
-                Some(s) => {
-                    if s.crawled_at.is_some() {
-                        return Err(anyhow::anyhow!("Session is already crawled."));
-                    }
-                },
-                None => return Err(anyhow::anyhow!("No session found in DB.")),
-            };
+            let mut transaction = options.pool.begin().await?;
+            transition(&mut transaction, &session, SessionStatus::Crawling, None).await?;
+            transaction.commit().await?;
 
             session
         }
         None => {
-            let session = Uuid::new_v4();
-            tracing::info!("New session: {}", session);
+            create_session(
+                &options.pool,
+                &options.config.list_page_url,
+                &options.config.queue_priorities,
+            )
+            .await?
+        }
+    };
+
+    crawl_session(options, session).await
+}
 
+/// Saves a new session of `search`, crawling from its first list page.
+pub async fn create_session(
+    pool: &PgPool,
+    search: &url::Url,
+    priorities: &QueuePriorities,
+) -> anyhow::Result<Uuid> {
+    let session = Uuid::new_v4();
+    tracing::info!("New session: {}", session);
+
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO sessions
+        (session, created_at, search)
+        VALUES ($1, CURRENT_TIMESTAMP, $2)
+        "#,
+        &session,
+        search.as_str(),
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed saving new session.")?;
+    transition(&mut transaction, &session, SessionStatus::Crawling, None).await?;
+
+    insert_job(
+        &mut transaction,
+        &session,
+        search,
+        PageType::OlxList,
+        priorities.get(PageType::OlxList),
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(session)
+}
+
+/// Runs the queue of a crawling session to the end, or until its budget runs
+/// out, then marks it crawled, partially crawled, failed or aborted.
+pub async fn crawl_session<'a>(options: &'a CrawlOptions<'a>, session: Uuid) -> anyhow::Result<()> {
+    let mut transaction = options.pool.begin().await?;
+    set_budget(&mut transaction, &session, &options.budget)
+        .await
+        .context("Failed to set the crawl budget.")?;
+    transaction.commit().await?;
+
+    let (status, reason) = match process_jobs(
+        &options.pool,
+        &session,
+        &options.config.retry_policies,
+        &options.config.queue_priorities,
+        options.shutdown.clone(),
+    )
+    .await
+    {
+        Ok(JobsOutcome::Done) => (SessionStatus::Crawled, None),
+        Ok(JobsOutcome::Exhausted(reason)) => {
+            println!(
+                "Crawl stopped early ({}), resume it with `crawl {}`.",
+                reason, session
+            );
+            (SessionStatus::PartiallyCrawled, Some(reason))
+        }
+        Ok(JobsOutcome::Interrupted(signal)) => {
             let mut transaction = options.pool.begin().await?;
-            sqlx::query!(
-                r#"
-                INSERT INTO sessions
-                (session, created_at)
-                VALUES ($1, CURRENT_TIMESTAMP)
-                "#,
-                &session
+            transition(
+                &mut transaction,
+                &session,
+                SessionStatus::Aborted,
+                Some(&format!("Interrupted by {}.", signal)),
             )
-            .execute(&mut transaction)
-            .await
-            .context("Failed saving new session.")?;
-
-            insert_job(
+            .await?;
+            transaction.commit().await?;
+            return Err(anyhow::anyhow!(
+                "Crawl interrupted, resume it with `crawl {}`.",
+                session
+            ));
+        }
+        Err(e) => {
+            let mut transaction = options.pool.begin().await?;
+            transition(
                 &mut transaction,
                 &session,
-                &options.config.list_page_url,
-                PageType::OlxList,
+                SessionStatus::Failed,
+                Some(&format!("{:#}", e)),
             )
             .await?;
-
             transaction.commit().await?;
-
-            session
+            return Err(e);
         }
     };
 
-    if process_jobs(&options.pool, &session).await.is_ok() {
-        let result = sqlx::query!(
-            r#"
-            UPDATE sessions
-            SET crawled_at=CURRENT_TIMESTAMP
-            WHERE session=$1
-            "#,
-            &session
-        )
-        .execute(&options.pool)
-        .await?;
-
-        if result.rows_affected() == 0 {
-            return Err(anyhow::anyhow!("No session has been updated, lol wut?"));
-        }
+    let mut transaction = options.pool.begin().await?;
+    transition(&mut transaction, &session, status, reason.as_deref()).await?;
+    // Ads a partial crawl did not reach are not gone, they're left as they are
+    // until a crawl of the session completes.
+    if status == SessionStatus::Crawled {
+        update_ads(&mut transaction, &session)
+            .await
+            .context("Failed updating ads.")?;
     }
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Where a reused session stood and what is left in its queue.
+async fn print_resume_summary(pool: &PgPool, session: &Uuid) -> anyhow::Result<()> {
+    let s = sqlx::query!(
+        r#"
+        SELECT
+          status AS "status: SessionStatus",
+          status_reason
+        FROM sessions
+        WHERE session=$1
+        "#,
+        session,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed retrieving session.")?
+    .ok_or_else(|| anyhow::anyhow!("No session found in DB."))?;
+
+    let remaining = sqlx::query_as!(
+        QueueCount,
+        r#"
+        SELECT
+          page_type AS "page_type: _",
+          status::TEXT AS "status!",
+          COUNT(*) AS "count!"
+        FROM crawler_queue
+        WHERE
+          session=$1
+          AND status IN ('new', 'retrying')
+        GROUP BY page_type, status
+        ORDER BY page_type, status
+        "#,
+        session,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed counting the remaining jobs.")?;
+
+    println!(
+        "Resuming session {}, {}{}",
+        session,
+        s.status,
+        s.status_reason
+            .map_or(String::new(), |reason| format!(" ({})", reason))
+    );
+    match remaining.is_empty() {
+        true => println!("No new or retrying jobs left."),
+        false => remaining.iter().for_each(|r| {
+            println!(
+                "{:<12} | {:<10} | {}",
+                r.page_type.to_string(),
+                r.status,
+                r.count
+            );
+        }),
+    };
 
     Ok(())
 }
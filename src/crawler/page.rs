@@ -4,7 +4,7 @@ use url::Url;
 
 use crate::{
     config::TEST_ASSETS_DIR,
-    page::{PageType, PageUrl, SavedPage},
+    page::{PageType, PageUrl, SavedPage, PAGES_SAVED_CHANNEL},
     util::PgTransaction,
 };
 
@@ -32,7 +32,7 @@ pub fn get_list_urls(document: &Html) -> anyhow::Result<Vec<PageUrl>> {
     Ok(document
         .select(&selector)
         .flat_map(|item| item.value().attr("href"))
-        .flat_map(|url| PageUrl::parse(url))
+        .flat_map(PageUrl::parse)
         .collect::<Vec<_>>())
 }
 
@@ -52,6 +52,27 @@ pub async fn get_page(url: &Url) -> anyhow::Result<String> {
         .context("Failed to parse the body as text")
 }
 
+/// Checks that a fetched page is the page we asked for and not an error,
+/// captcha or consent page served with a 200, by looking for the element the
+/// crawler or the extractor relies on.
+pub fn validate_page(page_type: PageType, content: &str) -> anyhow::Result<()> {
+    let selector = match page_type {
+        PageType::OlxList => "table#offers_table",
+        PageType::OlxItem => "script#olx-init-config",
+        PageType::StoriaItem => "script#__NEXT_DATA__",
+    };
+    let selector = scraper::Selector::parse(selector)
+        .map_err(|e| anyhow::anyhow!("Failed to parse selector {:?}", e))?;
+
+    match Html::parse_document(content).select(&selector).next() {
+        Some(_) => Ok(()),
+        None => Err(anyhow::anyhow!(
+            "Page does not look like a {} page.",
+            page_type
+        )),
+    }
+}
+
 #[tracing::instrument(skip_all, fields(url = %url))]
 pub async fn save_list_page_url<'a>(
     transaction: &mut PgTransaction<'a>,
@@ -125,6 +146,15 @@ pub async fn save_page<'a, 'b>(
         &page.page_type as &PageType,
         &page.content
     )
+    .execute(&mut *transaction)
+    .await?;
+
+    // Delivered on commit, lets a following extractor pick the page up.
+    sqlx::query!(
+        "SELECT pg_notify($1, $2)",
+        PAGES_SAVED_CHANNEL,
+        page.session.to_string(),
+    )
     .execute(transaction)
     .await?;
     Ok(())
@@ -137,8 +167,8 @@ pub fn save_test_asset(asset_name: &str, body: &str) -> anyhow::Result<()> {
 
 #[cfg(test)]
 mod tests {
-    use super::get_list_urls;
-    use crate::config::TEST_ASSETS_DIR;
+    use super::{get_list_urls, validate_page};
+    use crate::{config::TEST_ASSETS_DIR, page::PageType};
 
     #[test]
     fn can_find_list_items() {
@@ -153,4 +183,18 @@ mod tests {
         // There are usually 45 items, but some might be ads or idk
         assert!(results >= 38);
     }
+
+    #[test]
+    fn validates_pages() {
+        let list = std::fs::read_to_string(format!("{}/grid-list-page.html", TEST_ASSETS_DIR)).unwrap();
+        let olx = std::fs::read_to_string("src/extract/test_assets/olx-item.html").unwrap();
+        let storia = std::fs::read_to_string("src/extract/test_assets/storia-item.html").unwrap();
+
+        assert!(validate_page(PageType::OlxList, &list).is_ok());
+        assert!(validate_page(PageType::OlxItem, &olx).is_ok());
+        assert!(validate_page(PageType::StoriaItem, &storia).is_ok());
+
+        assert!(validate_page(PageType::StoriaItem, &olx).is_err());
+        assert!(validate_page(PageType::OlxItem, "<html><body>Access denied</body></html>").is_err());
+    }
 }
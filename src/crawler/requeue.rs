@@ -0,0 +1,122 @@
+use anyhow::Context;
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    page::PageType,
+    util::{try_parse_session, PgTransaction},
+};
+
+use super::job::{notify_jobs, CrawlStatus};
+
+/// Puts jobs of a session back in the queue, by default the failed ones, so
+/// the next `crawl <session>` fetches them again. A crawled session is
+/// reopened by that crawl.
+#[derive(clap::Args)]
+pub struct CrawlRequeueCmd {
+    pub session: String,
+    #[arg(long, value_enum, default_value_t = CrawlStatus::Failed)]
+    pub status: CrawlStatus,
+    #[arg(long, value_enum)]
+    pub page_type: Option<PageType>,
+    /// Only jobs with an error LIKE this pattern (e.g. '%429%').
+    #[arg(long)]
+    pub error_like: Option<String>,
+    /// Keep the errors of previous attempts, they still count towards the retry limit.
+    #[arg(long)]
+    pub keep_retries: bool,
+    /// Show how many jobs would be requeued, without requeueing them.
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+pub struct RequeueFilter<'a> {
+    pub status: CrawlStatus,
+    pub page_type: Option<PageType>,
+    pub error_like: Option<&'a str>,
+    pub keep_retries: bool,
+}
+
+impl CrawlRequeueCmd {
+    pub fn work(&self, config: &Config) -> anyhow::Result<()> {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("Failed building the Runtime")
+            .block_on(async move {
+                let session = try_parse_session(&self.session)?;
+                let pool = sqlx::postgres::PgPoolOptions::new()
+                    .connect(config.database_url.as_ref())
+                    .await
+                    .context("Failed to establish connection to postgres.")?;
+
+                let filter = RequeueFilter {
+                    status: self.status,
+                    page_type: self.page_type,
+                    error_like: self.error_like.as_deref(),
+                    keep_retries: self.keep_retries,
+                };
+
+                let mut transaction = pool.begin().await?;
+                let requeued = requeue_jobs(&mut transaction, &session, &filter)
+                    .await
+                    .context("Failed to requeue the jobs.")?;
+
+                match self.dry_run {
+                    true => {
+                        transaction.rollback().await?;
+                        println!("Would requeue {} jobs.", requeued);
+                    }
+                    false => {
+                        transaction.commit().await?;
+                        println!(
+                            "Requeued {} jobs, resume with `crawl {}`.",
+                            requeued, session
+                        );
+                    }
+                };
+
+                Ok(())
+            })
+    }
+}
+
+/// Resets the matching jobs to new, due right away. Returns how many matched.
+pub async fn requeue_jobs<'a>(
+    transaction: &mut PgTransaction<'a>,
+    session: &Uuid,
+    filter: &RequeueFilter<'_>,
+) -> sqlx::Result<u64> {
+    let requeued = sqlx::query!(
+        r#"
+        UPDATE crawler_queue
+        SET
+          status='new',
+          failure_error=NULL,
+          failure_status=NULL,
+          retries=CASE WHEN $5 THEN retries ELSE '{}' END,
+          not_before=CURRENT_TIMESTAMP
+        WHERE
+          session=$1
+          AND status=$2
+          AND ($3::page_type IS NULL OR page_type=$3)
+          AND (
+            $4::TEXT IS NULL
+            OR failure_error LIKE $4
+            OR EXISTS (SELECT 1 FROM unnest(retries) AS r (error) WHERE r.error LIKE $4)
+          )
+        "#,
+        session,
+        filter.status as CrawlStatus,
+        filter.page_type as Option<PageType>,
+        filter.error_like,
+        filter.keep_retries,
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+
+    // A crawl still running picks them up right away.
+    notify_jobs(transaction, session).await?;
+    Ok(requeued)
+}
@@ -0,0 +1,135 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::job::notify_jobs;
+
+/// How long a claimed job belongs to its worker, fetching a page must take
+/// less than that.
+pub const LEASE: std::time::Duration = std::time::Duration::from_secs(300);
+
+const HEARTBEAT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Heartbeats a worker can miss before its jobs are claimed by others.
+const MISSED_HEARTBEATS: u32 = 3;
+
+/// Registers this process as a worker of `session`.
+pub async fn register_worker(pool: &PgPool, session: &Uuid) -> sqlx::Result<Uuid> {
+    let worker = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO crawler_workers
+        (worker, session, hostname, pid, started_at, heartbeat_at)
+        VALUES ($1, $2, $3, $4, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+        "#,
+        worker,
+        session,
+        hostname(),
+        std::process::id() as i32,
+    )
+    .execute(pool)
+    .await?;
+
+    tracing::info!("Registered worker {}.", worker);
+    Ok(worker)
+}
+
+/// Marks the worker stopped and gives its claimed jobs back.
+pub async fn deregister_worker(pool: &PgPool, worker: &Uuid) -> sqlx::Result<()> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        UPDATE crawler_queue
+        SET
+          claimed_by=NULL,
+          lease_until=NULL
+        WHERE claimed_by=$1
+        "#,
+        worker,
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        "UPDATE crawler_workers SET stopped_at=CURRENT_TIMESTAMP WHERE worker=$1",
+        worker,
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await
+}
+
+/// Tells the other workers this one is alive and takes back the jobs of the
+/// ones that aren't, until aborted.
+pub async fn heartbeat(pool: PgPool, worker: Uuid, session: Uuid) {
+    let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = sqlx::query!(
+            "UPDATE crawler_workers SET heartbeat_at=CURRENT_TIMESTAMP WHERE worker=$1",
+            worker,
+        )
+        .execute(&pool)
+        .await
+        {
+            tracing::error!("Failed to send heartbeat ({:?})", e);
+            continue;
+        }
+
+        match reclaim_jobs(&pool, &session).await {
+            Ok(0) => {}
+            Ok(reclaimed) => tracing::warn!("Reclaimed {} jobs of dead workers.", reclaimed),
+            Err(e) => tracing::error!("Failed to reclaim jobs ({:?})", e),
+        }
+    }
+}
+
+/// Releases the jobs whose lease ran out or whose worker stopped or missed
+/// its heartbeats, so they can be claimed again.
+pub async fn reclaim_jobs(pool: &PgPool, session: &Uuid) -> sqlx::Result<u64> {
+    let stale_after = (HEARTBEAT_INTERVAL * MISSED_HEARTBEATS).as_secs_f64();
+    let mut transaction = pool.begin().await?;
+    let reclaimed = sqlx::query!(
+        r#"
+        UPDATE crawler_queue
+        SET
+          claimed_by=NULL,
+          lease_until=NULL
+        WHERE
+          session=$1
+          AND claimed_by IS NOT NULL
+          AND (
+            lease_until < CURRENT_TIMESTAMP
+            OR claimed_by IN (
+              SELECT worker
+              FROM crawler_workers
+              WHERE
+                session=$1
+                AND (
+                  stopped_at IS NOT NULL
+                  OR heartbeat_at < CURRENT_TIMESTAMP - make_interval(secs => $2)
+                )
+            )
+          )
+        "#,
+        session,
+        stale_after,
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+
+    if reclaimed > 0 {
+        notify_jobs(&mut transaction, session).await?;
+    }
+    transaction.commit().await?;
+    Ok(reclaimed)
+}
+
+fn hostname() -> String {
+    std::fs::read_to_string("/etc/hostname")
+        .map(|h| h.trim().to_string())
+        .ok()
+        .filter(|h| !h.is_empty())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .unwrap_or_else(|| "unknown".into())
+}
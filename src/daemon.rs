@@ -0,0 +1,404 @@
+use std::{path::PathBuf, str::FromStr};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Connection, PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    crawler::{budget::CrawlBudget, crawl_session, create_session, CrawlOptions},
+    dedup::assign_clusters,
+    extract::extractor::{extract, ExtractOptions},
+    session::prune::{prune_pages, PrunePolicy},
+    util::{parse_duration, shutdown_signal, Shutdown},
+};
+
+/// Crawls the configured searches on their schedules, extracts every crawled
+/// session, clusters duplicate ads and prunes old pages. Every run is recorded in `schedule_runs`.
+#[derive(clap::Args)]
+pub struct DaemonCmd {
+    /// JSON file with the searches to crawl, see `searches.example.json`.
+    #[arg(long, default_value = "searches.json")]
+    pub searches: PathBuf,
+    /// Number of concurrent extraction workers per session.
+    #[arg(
+        long,
+        default_value_t = num_cpus::get(),
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub workers: usize,
+}
+
+#[derive(serde::Deserialize)]
+struct SearchEntry {
+    name: String,
+    url: String,
+    cron: String,
+    keep_last: Option<i64>,
+    prune_after: Option<String>,
+}
+
+pub struct ScheduledSearch {
+    pub name: String,
+    pub url: url::Url,
+    /// Cron expression with seconds, e.g. `0 0 */6 * * *` every 6 hours.
+    pub schedule: cron::Schedule,
+    /// Keep the pages of the N most recent sessions of the search.
+    pub keep_last: Option<i64>,
+    /// Prune the pages of sessions older than this.
+    pub prune_after: Option<chrono::Duration>,
+}
+
+impl ScheduledSearch {
+    fn retention(&self) -> Option<PrunePolicy<'_>> {
+        (self.keep_last.is_some() || self.prune_after.is_some()).then(|| PrunePolicy {
+            keep_last: self.keep_last,
+            created_before: self.prune_after.map(|d| Utc::now() - d),
+            search: Some(self.url.as_str()),
+        })
+    }
+}
+
+pub fn parse_searches(json: &str) -> anyhow::Result<Vec<ScheduledSearch>> {
+    let entries: Vec<SearchEntry> =
+        serde_json::from_str(json).context("Failed to parse the searches.")?;
+
+    let searches = entries
+        .into_iter()
+        .map(|e| {
+            if e.keep_last.is_some_and(|n| n < 0) {
+                return Err(anyhow::anyhow!("keep_last of {} is negative.", e.name));
+            }
+            Ok(ScheduledSearch {
+                url: url::Url::parse(&e.url)
+                    .with_context(|| format!("Failed to parse the URL of {}.", e.name))?,
+                schedule: cron::Schedule::from_str(&e.cron)
+                    .with_context(|| format!("Failed to parse the cron of {}.", e.name))?,
+                keep_last: e.keep_last,
+                prune_after: e
+                    .prune_after
+                    .as_deref()
+                    .map(parse_duration)
+                    .transpose()
+                    .with_context(|| format!("Failed to parse prune_after of {}.", e.name))?,
+                name: e.name,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    if let Some(search) = searches
+        .iter()
+        .enumerate()
+        .find(|(i, s)| searches[..*i].iter().any(|other| other.url == s.url))
+        .map(|(_, s)| s)
+    {
+        return Err(anyhow::anyhow!("{} is listed more than once.", search.url));
+    }
+
+    Ok(searches)
+}
+
+/// Runs the scheduled searches, see `DaemonCmd`.
+pub struct Daemon<'a> {
+    pub config: &'a Config,
+    pub pool: PgPool,
+    pub shutdown: Shutdown,
+    pub workers: usize,
+}
+
+impl DaemonCmd {
+    pub fn work(&self, config: &Config) -> anyhow::Result<()> {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("Failed building the Runtime")
+            .block_on(async move {
+                tracing_subscriber::fmt::init();
+                let searches = std::fs::read_to_string(&self.searches)
+                    .with_context(|| format!("Failed to read {}.", self.searches.display()))
+                    .and_then(|json| parse_searches(&json))?;
+
+                let daemon = Daemon {
+                    config,
+                    pool: sqlx::postgres::PgPoolOptions::new()
+                        // A crawl holds a couple of connections, plus the
+                        // extraction workers of one session at a time.
+                        .max_connections((self.workers + 2 + 3 * searches.len()) as u32)
+                        .connect_lazy(config.database_url.as_ref())
+                        .context("Failed to establish lazy connection to postgres.")?,
+                    shutdown: shutdown_signal()?,
+                    workers: self.workers,
+                };
+
+                tracing::info!("Scheduling {} searches.", searches.len());
+                futures::future::join_all(searches.iter().map(|s| daemon.run_schedule(s))).await;
+
+                tracing::info!("Daemon stopped.");
+                Ok(())
+            })
+    }
+}
+
+impl Daemon<'_> {
+    /// Waits for every tick of the schedule and runs the search, until shutdown.
+    /// Ticks passed while a run was still going are recorded as skipped.
+    async fn run_schedule(&self, search: &ScheduledSearch) {
+        let mut shutdown = self.shutdown.clone();
+        loop {
+            let scheduled_at = match search.schedule.upcoming(Utc).next() {
+                Some(scheduled_at) => scheduled_at,
+                None => {
+                    tracing::info!("No more runs scheduled for {}.", search.name);
+                    return;
+                }
+            };
+            tracing::info!("Next run of {} at {}.", search.name, scheduled_at);
+
+            let wait = (scheduled_at - Utc::now()).to_std().unwrap_or_default();
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                Ok(()) = shutdown.changed() => {}
+            };
+            if shutdown.borrow().is_some() {
+                return;
+            }
+
+            if let Err(e) = self.run_search(search, scheduled_at).await {
+                tracing::error!("Run of {} failed ({:?})", search.name, e);
+            }
+            if let Err(e) = self
+                .skip_missed_ticks(search, scheduled_at, Utc::now())
+                .await
+            {
+                tracing::error!(
+                    "Failed to record the missed runs of {} ({:?})",
+                    search.name,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Records the ticks of the schedule after `scheduled_at` and up to `now`
+    /// as skipped runs. Returns how many there were.
+    pub async fn skip_missed_ticks(
+        &self,
+        search: &ScheduledSearch,
+        scheduled_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> sqlx::Result<usize> {
+        let missed = search
+            .schedule
+            .after(&scheduled_at)
+            .take_while(|tick| *tick <= now)
+            .collect::<Vec<_>>();
+        for tick in &missed {
+            start_run(&self.pool, search, *tick, false).await?;
+        }
+        if !missed.is_empty() {
+            tracing::warn!(
+                "{} runs of {} came due while it was running, skipped.",
+                missed.len(),
+                search.name
+            );
+        }
+        Ok(missed.len())
+    }
+
+    /// Crawls, extracts and prunes, unless another run of the same search
+    /// holds its lock, possibly in another daemon.
+    #[tracing::instrument(skip_all, fields(search = %search.name))]
+    pub async fn run_search(
+        &self,
+        search: &ScheduledSearch,
+        scheduled_at: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        // Advisory locks belong to the connection, a dedicated one keeps the
+        // pool from handing it out while the lock is held.
+        let mut lock = PgConnection::connect(self.config.database_url.as_ref())
+            .await
+            .context("Failed to connect for the search lock.")?;
+        let locked = sqlx::query_scalar!(
+            "SELECT pg_try_advisory_lock(hashtextextended($1, 0))",
+            search.url.as_str()
+        )
+        .fetch_one(&mut lock)
+        .await
+        .context("Failed to lock the search.")?
+        .unwrap_or(false);
+
+        let run = start_run(&self.pool, search, scheduled_at, locked)
+            .await
+            .context("Failed to record the run.")?;
+        if !locked {
+            tracing::warn!("Another run of {} is still going, skipped.", search.name);
+            return Ok(());
+        }
+
+        let result = self.crawl_extract_prune(search, run).await;
+        finish_run(&self.pool, run, &result)
+            .await
+            .context("Failed to record the end of the run.")?;
+
+        // Closing the connection releases the lock.
+        lock.close().await.ok();
+        result.map(|_| ())
+    }
+
+    /// Returns how many pages were pruned.
+    async fn crawl_extract_prune(&self, search: &ScheduledSearch, run: i64) -> anyhow::Result<i64> {
+        let session =
+            create_session(&self.pool, &search.url, &self.config.queue_priorities).await?;
+        set_run_session(&self.pool, run, &session)
+            .await
+            .context("Failed to record the run session.")?;
+
+        let crawl_options = CrawlOptions {
+            budget: CrawlBudget::default(),
+            config: self.config,
+            pool: self.pool.clone(),
+            session: Some(session),
+            shutdown: self.shutdown.clone(),
+        };
+        crawl_session(&crawl_options, session).await?;
+
+        let extract_options = ExtractOptions {
+            config: self.config,
+            follow: false,
+            pool: self.pool.clone(),
+            reextract_below: None,
+            session,
+            shutdown: self.shutdown.clone(),
+            workers: self.workers,
+        };
+        extract(&extract_options).await?;
+
+        // New classifieds may be duplicates of known ads.
+        assign_clusters(&self.pool)
+            .await
+            .context("Failed to cluster the ads.")?;
+
+        let policy = match search.retention() {
+            Some(policy) => policy,
+            None => return Ok(0),
+        };
+        let mut transaction = self.pool.begin().await?;
+        let pruned = prune_pages(&mut transaction, &policy)
+            .await
+            .context("Failed to prune pages.")?;
+        transaction.commit().await?;
+
+        let pages = pruned.iter().map(|p| p.pages).sum();
+        tracing::info!("Pruned {} pages of {} sessions.", pages, pruned.len());
+        Ok(pages)
+    }
+}
+
+async fn start_run(
+    pool: &PgPool,
+    search: &ScheduledSearch,
+    scheduled_at: DateTime<Utc>,
+    locked: bool,
+) -> sqlx::Result<i64> {
+    sqlx::query_scalar!(
+        r#"
+        INSERT INTO schedule_runs
+        (name, search, scheduled_at, started_at, finished_at, status)
+        VALUES (
+          $1,
+          $2,
+          $3,
+          CURRENT_TIMESTAMP,
+          CASE WHEN $4 THEN NULL ELSE CURRENT_TIMESTAMP END,
+          CASE WHEN $4 THEN 'running' ELSE 'skipped' END::schedule_run_status
+        )
+        RETURNING id
+        "#,
+        &search.name,
+        search.url.as_str(),
+        scheduled_at,
+        locked,
+    )
+    .fetch_one(pool)
+    .await
+}
+
+async fn set_run_session(pool: &PgPool, run: i64, session: &Uuid) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE schedule_runs SET session=$2 WHERE id=$1",
+        run,
+        session
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())
+}
+
+async fn finish_run(pool: &PgPool, run: i64, result: &anyhow::Result<i64>) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE schedule_runs
+        SET
+          finished_at=CURRENT_TIMESTAMP,
+          status=CASE WHEN $2::TEXT IS NULL THEN 'completed' ELSE 'failed' END::schedule_run_status,
+          error=$2,
+          pruned_pages=$3
+        WHERE id=$1
+        "#,
+        run,
+        result.as_ref().err().map(|e| format!("{:#}", e)),
+        result.as_ref().ok(),
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_searches;
+
+    #[test]
+    fn parses_searches() {
+        let searches = parse_searches(
+            r#"[
+              {
+                "name": "brasov",
+                "url": "https://www.olx.ro/imobiliare/apartamente-garsoniere-de-inchiriat/brasov/",
+                "cron": "0 0 */6 * * *",
+                "keep_last": 5,
+                "prune_after": "30d"
+              },
+              {
+                "name": "cluj",
+                "url": "https://www.olx.ro/imobiliare/apartamente-garsoniere-de-inchiriat/cluj-napoca/",
+                "cron": "0 30 8 * * Mon-Fri"
+              }
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(searches.len(), 2);
+        assert_eq!(searches[0].prune_after, Some(chrono::Duration::days(30)));
+        assert!(searches[0].retention().is_some());
+        assert!(searches[1].retention().is_none());
+        assert!(searches[1].schedule.upcoming(chrono::Utc).next().is_some());
+
+        assert!(
+            parse_searches(r#"[{"name": "x", "url": "https://olx.ro/", "cron": "daily"}]"#)
+                .is_err()
+        );
+        assert!(parse_searches(
+            r#"[{"name": "x", "url": "https://olx.ro/", "cron": "0 0 * * * *", "keep_last": -1}]"#
+        )
+        .is_err());
+        assert!(parse_searches(
+            r#"[
+              {"name": "a", "url": "https://olx.ro/", "cron": "0 0 * * * *"},
+              {"name": "b", "url": "https://olx.ro/", "cron": "0 0 * * * *"}
+            ]"#
+        )
+        .is_err());
+    }
+}
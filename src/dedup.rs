@@ -0,0 +1,407 @@
+use std::collections::BTreeMap;
+
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::{config::Config, extract::text::normalize, util::Currency};
+
+/// Prices of the same property may differ a bit between agencies.
+const PRICE_TOLERANCE: f64 = 0.05;
+
+/// Surfaces are rounded differently, or built vs usable area is mixed up.
+const SURFACE_TOLERANCE: f64 = 0.03;
+const SURFACE_MIN_TOLERANCE: f64 = 2.0;
+
+/// Share of title words two ads must have in common.
+const TITLE_SIMILARITY: f64 = 0.6;
+
+/// OLX only publishes approximate coordinates, anything further is another place.
+const MAX_DISTANCE_KM: f64 = 10.0;
+
+/// Closer than this, two ads are taken to be at the same place.
+const SAME_PLACE_KM: f64 = 0.5;
+
+/// Clusters ads of the same property, cross posted on OLX and Storia or
+/// reposted, so reports can count properties instead of ads.
+#[derive(clap::Args)]
+pub struct DedupCmd {}
+
+impl DedupCmd {
+    pub fn work(&self, config: &Config) -> anyhow::Result<()> {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("Failed building the Runtime")
+            .block_on(async move {
+                tracing_subscriber::fmt::init();
+                let pool = sqlx::postgres::PgPoolOptions::new()
+                    .connect(config.database_url.as_ref())
+                    .await
+                    .context("Failed to establish connection to postgres.")?;
+
+                assign_clusters(&pool).await
+            })
+    }
+}
+
+/// What an ad is compared on, taken from its latest classified. Photos are
+/// left out, reposts upload them again under new CDN names so only hashing
+/// their content would match them, and that means downloading every photo.
+pub struct Fingerprint {
+    pub ad_id: String,
+    pub city: Option<String>,
+    pub coordinates: Option<(f64, f64)>,
+    pub currency: Option<Currency>,
+    pub floor: Option<i16>,
+    pub price: f64,
+    pub room_count: Option<i16>,
+    pub surface: Option<i32>,
+    /// Normalized title words, sorted and deduplicated.
+    pub title: Vec<String>,
+}
+
+impl Fingerprint {
+    pub fn title_words(title: &str) -> Vec<String> {
+        let mut words = normalize(title)
+            .split(' ')
+            .filter(|w| !w.is_empty())
+            .map(String::from)
+            .collect::<Vec<_>>();
+        words.sort();
+        words.dedup();
+        words
+    }
+
+    /// Whether both have a value and the values differ.
+    fn conflicts(&self, other: &Self) -> bool {
+        fn differ<T: PartialEq>(a: &Option<T>, b: &Option<T>) -> bool {
+            matches!((a, b), (Some(a), Some(b)) if a != b)
+        }
+
+        differ(&self.city, &other.city)
+            || differ(&self.currency, &other.currency)
+            || differ(&self.floor, &other.floor)
+            || differ(&self.room_count, &other.room_count)
+            || matches!(
+                (self.coordinates, other.coordinates),
+                (Some(a), Some(b)) if distance_km(a, b) > MAX_DISTANCE_KM
+            )
+    }
+
+    fn similar_price(&self, other: &Self) -> bool {
+        (self.price - other.price).abs() <= self.price.max(other.price) * PRICE_TOLERANCE
+    }
+
+    fn similar_surface(&self, other: &Self) -> bool {
+        match (self.surface, other.surface) {
+            (Some(a), Some(b)) => {
+                let (a, b) = (f64::from(a), f64::from(b));
+                (a - b).abs() <= (a.max(b) * SURFACE_TOLERANCE).max(SURFACE_MIN_TOLERANCE)
+            }
+            _ => false,
+        }
+    }
+
+    fn same_place(&self, other: &Self) -> bool {
+        matches!(
+            (self.coordinates, other.coordinates),
+            (Some(a), Some(b)) if distance_km(a, b) <= SAME_PLACE_KM
+        )
+    }
+
+    /// Jaccard index of the title words.
+    fn title_similarity(&self, other: &Self) -> f64 {
+        let common = self
+            .title
+            .iter()
+            .filter(|w| other.title.contains(w))
+            .count();
+        let total = self.title.len() + other.title.len() - common;
+        match total {
+            0 => 0.0,
+            total => common as f64 / total as f64,
+        }
+    }
+
+    /// Nothing known conflicts, the price and surface are close and either the
+    /// title or the place matches. Price and surface alone fit most studios
+    /// of a city.
+    pub fn is_duplicate(&self, other: &Self) -> bool {
+        !self.conflicts(other)
+            && self.similar_price(other)
+            && self.similar_surface(other)
+            && (self.title_similarity(other) >= TITLE_SIMILARITY || self.same_place(other))
+    }
+}
+
+/// Haversine distance between two (latitude, longitude) points.
+fn distance_km(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat1, lon1) = (a.0.to_radians(), a.1.to_radians());
+    let (lat2, lon2) = (b.0.to_radians(), b.1.to_radians());
+    let h = ((lat2 - lat1) / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+    2.0 * 6371.0 * h.sqrt().asin()
+}
+
+struct UnionFind {
+    parents: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(len: usize) -> Self {
+        Self {
+            parents: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, i: usize) -> usize {
+        let parent = self.parents[i];
+        if parent == i {
+            return i;
+        }
+        let root = self.find(parent);
+        self.parents[i] = root;
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parents[a.max(b)] = a.min(b);
+    }
+}
+
+/// Cluster ID of every ad, the smallest ad ID of its cluster so it's stable
+/// across runs as long as the cluster keeps that ad. Duplicates are
+/// transitive, A like B and B like C puts all three together, unless
+/// something known about A conflicts with C.
+pub fn cluster(fingerprints: &[Fingerprint]) -> Vec<(&str, &str)> {
+    // Sorted by price, only ads within the price tolerance need comparing.
+    let mut order = (0..fingerprints.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| fingerprints[a].price.total_cmp(&fingerprints[b].price));
+
+    let mut clusters = UnionFind::new(fingerprints.len());
+    // Members of every cluster, by root.
+    let mut members = (0..fingerprints.len()).map(|i| vec![i]).collect::<Vec<_>>();
+    for (i, &a) in order.iter().enumerate() {
+        for &b in order[i + 1..].iter() {
+            if !fingerprints[a].similar_price(&fingerprints[b]) {
+                break;
+            }
+            let (root_a, root_b) = (clusters.find(a), clusters.find(b));
+            if root_a == root_b || !fingerprints[a].is_duplicate(&fingerprints[b]) {
+                continue;
+            }
+            let conflicting = members[root_a].iter().any(|&x| {
+                members[root_b]
+                    .iter()
+                    .any(|&y| fingerprints[x].conflicts(&fingerprints[y]))
+            });
+            if !conflicting {
+                clusters.union(a, b);
+                let root = clusters.find(a);
+                let merged = std::mem::take(&mut members[root_a.max(root_b)]);
+                members[root].extend(merged);
+            }
+        }
+    }
+
+    let mut ids = BTreeMap::<usize, &str>::new();
+    for (i, f) in fingerprints.iter().enumerate() {
+        let id = ids.entry(clusters.find(i)).or_insert(&f.ad_id);
+        if f.ad_id.as_str() < *id {
+            *id = &f.ad_id;
+        }
+    }
+
+    fingerprints
+        .iter()
+        .enumerate()
+        .map(|(i, f)| (f.ad_id.as_str(), ids[&clusters.find(i)]))
+        .collect()
+}
+
+async fn load_fingerprints(pool: &PgPool) -> sqlx::Result<Vec<Fingerprint>> {
+    Ok(sqlx::query!(
+        r#"
+        SELECT DISTINCT ON (a.ad_id)
+          a.ad_id,
+          c.city,
+          c.currency AS "currency: Currency",
+          c.floor,
+          c.latitude,
+          c.longitude,
+          c.price AS "price!",
+          c.room_count,
+          c.surface,
+          c.title AS "title!"
+        FROM ads a
+        JOIN latest_classifieds c ON ad_id_from_url(c.url)=a.ad_id
+        JOIN sessions s ON s.session=c.session
+        ORDER BY a.ad_id, s.created_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| Fingerprint {
+        ad_id: r.ad_id,
+        city: r.city.map(|c| normalize(&c)),
+        coordinates: r.latitude.zip(r.longitude),
+        currency: r.currency,
+        floor: r.floor,
+        price: r.price,
+        room_count: r.room_count,
+        surface: r.surface,
+        title: Fingerprint::title_words(&r.title),
+    })
+    .collect())
+}
+
+/// Re-clusters every ad with an extracted classified.
+#[tracing::instrument(skip_all)]
+pub async fn assign_clusters(pool: &PgPool) -> anyhow::Result<()> {
+    let fingerprints = load_fingerprints(pool)
+        .await
+        .context("Failed to load the ads fingerprints.")?;
+    let (ad_ids, cluster_ids): (Vec<_>, Vec<_>) = cluster(&fingerprints)
+        .into_iter()
+        .map(|(ad_id, cluster_id)| (ad_id.to_string(), cluster_id.to_string()))
+        .unzip();
+
+    sqlx::query!(
+        r#"
+        UPDATE ads
+        SET property_cluster_id=c.cluster_id
+        FROM UNNEST($1::TEXT[], $2::TEXT[]) AS c (ad_id, cluster_id)
+        WHERE ads.ad_id=c.ad_id
+        "#,
+        &ad_ids,
+        &cluster_ids,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to save the clusters.")?;
+
+    let properties = cluster_ids
+        .iter()
+        .collect::<std::collections::HashSet<_>>()
+        .len();
+    tracing::info!(
+        "Clustered {} ads in {} properties.",
+        ad_ids.len(),
+        properties
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cluster, Fingerprint};
+    use crate::util::Currency;
+
+    fn fingerprint(ad_id: &str, price: f64, surface: Option<i32>, title: &str) -> Fingerprint {
+        Fingerprint {
+            ad_id: ad_id.into(),
+            city: Some("brasov".into()),
+            coordinates: None,
+            currency: Some(Currency::EUR),
+            floor: Some(2),
+            price,
+            room_count: Some(1),
+            surface,
+            title: Fingerprint::title_words(title),
+        }
+    }
+
+    #[test]
+    fn same_surface_and_price_is_not_enough() {
+        let mut olx = fingerprint("olx:a", 450.0, Some(42), "Garsoniera Uzina 2");
+        let mut storia = fingerprint(
+            "storia:b",
+            440.0,
+            Some(43),
+            "Inchiriere garsoniera lux, Urban Plaza",
+        );
+        assert!(!olx.is_duplicate(&storia));
+
+        olx.coordinates = Some((45.6427, 25.5887));
+        storia.coordinates = Some((45.6431, 25.5894));
+        assert!(olx.is_duplicate(&storia));
+    }
+
+    #[test]
+    fn similar_surface_and_title_is_a_duplicate() {
+        let a = fingerprint(
+            "olx:a",
+            450.0,
+            Some(41),
+            "Garsonieră Urban Plaza, prima închiriere",
+        );
+        let b = fingerprint(
+            "olx:b",
+            450.0,
+            Some(42),
+            "garsoniera Urban Plaza prima inchiriere!",
+        );
+        let c = fingerprint(
+            "olx:c",
+            450.0,
+            None,
+            "garsoniera Urban Plaza prima inchiriere!",
+        );
+
+        assert!(a.is_duplicate(&b));
+        assert!(!a.is_duplicate(&c));
+    }
+
+    #[test]
+    fn known_differences_are_not_duplicates() {
+        let a = fingerprint("olx:a", 450.0, Some(42), "Garsoniera Urban Plaza");
+        let mut b = fingerprint("olx:b", 450.0, Some(42), "Garsoniera Urban Plaza");
+        b.floor = Some(3);
+        let c = fingerprint("olx:c", 600.0, Some(42), "Garsoniera Urban Plaza");
+        let mut d = fingerprint("olx:d", 450.0, Some(42), "Garsoniera Urban Plaza");
+        d.coordinates = Some((45.66, 25.60));
+        let mut e = fingerprint("olx:e", 450.0, Some(42), "Garsoniera Urban Plaza");
+        e.coordinates = Some((44.43, 26.10));
+
+        assert!(!a.is_duplicate(&b));
+        assert!(!a.is_duplicate(&c));
+        assert!(a.is_duplicate(&d));
+        assert!(!d.is_duplicate(&e));
+    }
+
+    #[test]
+    fn clusters_are_transitive() {
+        let fingerprints = [
+            fingerprint("storia:c", 460.0, Some(42), "garsoniera"),
+            fingerprint("olx:b", 440.0, Some(42), "garsoniera"),
+            fingerprint("olx:z", 900.0, Some(80), "apartament 3 camere"),
+            fingerprint("olx:d", 450.0, Some(42), "garsoniera"),
+        ];
+
+        assert_eq!(
+            cluster(&fingerprints),
+            vec![
+                ("storia:c", "olx:b"),
+                ("olx:b", "olx:b"),
+                ("olx:z", "olx:z"),
+                ("olx:d", "olx:b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn clusters_keep_conflicting_ads_apart() {
+        // b doesn't tell its floor, a and c are on different ones.
+        let mut b = fingerprint("olx:b", 450.0, Some(42), "garsoniera");
+        b.floor = None;
+        let mut c = fingerprint("olx:c", 455.0, Some(42), "garsoniera");
+        c.floor = Some(4);
+        let fingerprints = [fingerprint("olx:a", 445.0, Some(42), "garsoniera"), b, c];
+
+        let clusters = cluster(&fingerprints);
+        assert_eq!(clusters[0].1, clusters[1].1);
+        assert_ne!(clusters[0].1, clusters[2].1);
+    }
+}
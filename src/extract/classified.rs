@@ -3,8 +3,11 @@ use uuid::Uuid;
 
 use crate::util::Currency;
 
-#[derive(sqlx::Type, Copy, Clone)]
+use super::text::{fold, Rule, Text};
+
+#[derive(sqlx::Type, serde::Serialize, Copy, Clone)]
 #[sqlx(type_name = "seller_type", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
 pub enum SellerType {
     Private,
     Company,
@@ -14,7 +17,7 @@ impl TryFrom<&str> for SellerType {
     type Error = anyhow::Error;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value.to_lowercase().as_str() {
+        match fold(value).as_str() {
             "privat" => Ok(Self::Private),
             "firma" => Ok(Self::Company),
             _ => Err(anyhow::anyhow!("Failed to parse seller type.")),
@@ -22,26 +25,36 @@ impl TryFrom<&str> for SellerType {
     }
 }
 
-#[derive(sqlx::Type, Copy, Clone)]
+#[derive(sqlx::Type, serde::Serialize, Copy, Clone)]
 #[sqlx(type_name = "property_layout", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
 pub enum Layout {
     Wagon,
     SemiFancy,
     Fancy,
 }
 
+const LAYOUT_RULES: [Rule<Layout>; 3] = [
+    Rule {
+        value: Layout::Wagon,
+        negated: None,
+        phrases: &["vagon"],
+    },
+    Rule {
+        value: Layout::SemiFancy,
+        negated: None,
+        phrases: &["semidecomandat*", "semi decomandat*"],
+    },
+    Rule {
+        value: Layout::Fancy,
+        negated: None,
+        phrases: &["decomandat*"],
+    },
+];
+
 impl Layout {
     pub fn find_in_str(s: &str) -> Option<Self> {
-        if s.contains("vagon") {
-            return Some(Self::Wagon);
-        }
-        if s.contains("semidecomandat") {
-            return Some(Self::SemiFancy);
-        }
-        if s.contains("decomandat") {
-            return Some(Self::Fancy);
-        }
-        None
+        Text::new(s).find(&LAYOUT_RULES)
     }
 }
 
@@ -49,7 +62,7 @@ impl TryFrom<&str> for Layout {
     type Error = anyhow::Error;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value.to_lowercase().as_str() {
+        match fold(value).as_str() {
             "vagon" => Ok(Self::Wagon),
             "semidecomandat" => Ok(Self::SemiFancy),
             "decomandat" => Ok(Self::Fancy),
@@ -58,31 +71,79 @@ impl TryFrom<&str> for Layout {
     }
 }
 
-#[derive(sqlx::Type, Copy, Clone)]
+#[derive(sqlx::Type, serde::Serialize, Copy, Clone, PartialEq, Eq, Debug)]
 #[sqlx(type_name = "cardinal_direction", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
 pub enum CardinalDirection {
     North,
     South,
     East,
     West,
+    NorthEast,
+    SouthEast,
+    SouthWest,
+    NorthWest,
 }
 
+impl sqlx::postgres::PgHasArrayType for CardinalDirection {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_cardinal_direction")
+    }
+}
+
+/// Composite directions come first, so "sud-est" is not also read as "sud"
+/// and "est". Their second word is matched exactly, "sud este" is "south is".
+const CARDINAL_DIRECTION_RULES: [Rule<CardinalDirection>; 8] = [
+    Rule {
+        value: CardinalDirection::NorthEast,
+        negated: None,
+        phrases: &["nord est", "nord estic*", "nordest*"],
+    },
+    Rule {
+        value: CardinalDirection::SouthEast,
+        negated: None,
+        phrases: &["sud est", "sud estic*", "sudest*"],
+    },
+    Rule {
+        value: CardinalDirection::SouthWest,
+        negated: None,
+        phrases: &["sud vest", "sud vestic*", "sudvest*"],
+    },
+    Rule {
+        value: CardinalDirection::NorthWest,
+        negated: None,
+        phrases: &["nord vest", "nord vestic*", "nordvest*"],
+    },
+    Rule {
+        value: CardinalDirection::South,
+        negated: None,
+        phrases: &["la sud", "spre sud", "orientat* sud", "sudic*"],
+    },
+    Rule {
+        value: CardinalDirection::East,
+        negated: None,
+        phrases: &["la est", "spre est", "orientat* est", "estic*"],
+    },
+    Rule {
+        value: CardinalDirection::West,
+        negated: None,
+        phrases: &["la vest", "spre vest", "orientat* vest", "vestic*"],
+    },
+    Rule {
+        value: CardinalDirection::North,
+        negated: None,
+        phrases: &["la nord", "spre nord", "orientat* nord", "nordic*"],
+    },
+];
+
 impl CardinalDirection {
     pub fn find_in_str(s: &str) -> Option<Self> {
-        let s = s.to_lowercase();
-        if s.contains("la sud") || s.contains("sudica") {
-            return Some(Self::South);
-        }
-        if s.contains("la est") || s.contains("estica") {
-            return Some(Self::East);
-        }
-        if s.contains("la vest") || s.contains("vestica") {
-            return Some(Self::West);
-        }
-        if s.contains("la nord") || s.contains("nordica") {
-            return Some(Self::North);
-        }
-        None
+        Text::new(s).find(&CARDINAL_DIRECTION_RULES)
+    }
+
+    /// Every exposure mentioned, for flats facing more than one side.
+    pub fn find_all_in_str(s: &str) -> Vec<Self> {
+        Text::new(s).find_all(&CARDINAL_DIRECTION_RULES)
     }
 }
 
@@ -90,70 +151,398 @@ impl TryFrom<&str> for CardinalDirection {
     type Error = anyhow::Error;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value {
+        match fold(value).as_str() {
             "north" | "nord" | "nordic" => Ok(Self::North),
             "south" | "sud" | "sudic" => Ok(Self::South),
             "east" | "est" | "estic" => Ok(Self::East),
             "west" | "vest" | "vestic" => Ok(Self::West),
-            _ => Err(anyhow::anyhow!("Failed to parse cardinal direction.")),
+            "north_east" | "northeast" | "nord-est" | "nord est" => Ok(Self::NorthEast),
+            "south_east" | "southeast" | "sud-est" | "sud est" => Ok(Self::SouthEast),
+            "south_west" | "southwest" | "sud-vest" | "sud vest" => Ok(Self::SouthWest),
+            "north_west" | "northwest" | "nord-vest" | "nord vest" => Ok(Self::NorthWest),
+            _ => Err(anyhow::anyhow!(
+                "Failed to parse cardinal direction \"{}\".",
+                value
+            )),
         }
     }
 }
 
-#[derive(sqlx::Type, Copy, Clone)]
+#[derive(sqlx::Type, serde::Serialize, Copy, Clone)]
 #[sqlx(type_name = "property_type", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
 pub enum PropertyType {
     Apartment,
     House,
+    /// One room flat ("garsoniera").
+    Studio,
+    /// A room in a shared flat or a dorm.
+    Room,
+    Duplex,
+    Penthouse,
+    Land,
+    Commercial,
+    Office,
 }
 
-const APARTMENT_MATCHES: [&str; 6] = [
-    "apartament",
-    "Apartament",
-    "garsoniera",
-    "Garsoniera",
-    "studio",
-    "Studio",
+/// The more specific types come first, a "duplex" is an "apartament" too. Homes
+/// go before offices and commercial spaces, flats mention "birou" or "Hala
+/// Traian" far more often than the other way around.
+const PROPERTY_TYPE_RULES: [Rule<PropertyType>; 9] = [
+    Rule {
+        value: PropertyType::Penthouse,
+        negated: None,
+        phrases: &["penthouse"],
+    },
+    Rule {
+        value: PropertyType::Duplex,
+        negated: None,
+        phrases: &["duplex"],
+    },
+    Rule {
+        value: PropertyType::Studio,
+        negated: None,
+        phrases: &["garsonier*", "studio"],
+    },
+    Rule {
+        value: PropertyType::Room,
+        negated: None,
+        phrases: &[
+            "camera de inchiriat",
+            "camera in apartament",
+            "camera intr un apartament",
+            "camera in regim hotelier",
+            "camera camin",
+            "camera in camin",
+            "loc in camera",
+        ],
+    },
+    Rule {
+        value: PropertyType::Apartment,
+        negated: None,
+        phrases: &["apartament*"],
+    },
+    Rule {
+        value: PropertyType::House,
+        negated: None,
+        phrases: &["casa", "case", "vila", "vile"],
+    },
+    Rule {
+        value: PropertyType::Office,
+        negated: None,
+        phrases: &["birou", "birouri", "spatiu de birouri", "spatii de birouri"],
+    },
+    Rule {
+        value: PropertyType::Commercial,
+        negated: None,
+        phrases: &[
+            "spatiu comercial",
+            "spatii comerciale",
+            "spatiu industrial",
+            "hala",
+        ],
+    },
+    Rule {
+        value: PropertyType::Land,
+        negated: None,
+        phrases: &["teren", "terenuri", "teren intravilan", "teren extravilan"],
+    },
 ];
 
-const HOUSE_MATCHES: [&str; 4] = ["casa", "Casa", "vila", "Vila"];
-
 impl PropertyType {
     pub fn find_in_str(value: &str) -> Option<Self> {
-        if APARTMENT_MATCHES.iter().any(|&m| value.contains(m)) {
-            return Some(Self::Apartment);
+        Text::new(value).find(&PROPERTY_TYPE_RULES)
+    }
+}
+
+impl TryFrom<&str> for PropertyType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match fold(value).as_str() {
+            "apartament" | "apartment" | "flat" => Ok(Self::Apartment),
+            "garsoniera" | "studio" => Ok(Self::Studio),
+            "camera" | "room" => Ok(Self::Room),
+            "duplex" => Ok(Self::Duplex),
+            "penthouse" => Ok(Self::Penthouse),
+            "casa" | "vila" | "house" => Ok(Self::House),
+            "teren" | "terrain" => Ok(Self::Land),
+            "spatiu comercial" | "commercial" => Ok(Self::Commercial),
+            "birou" | "birouri" | "office" => Ok(Self::Office),
+            _ => Err(anyhow::anyhow!("Failed to parse property type \"{}\".", value)),
         }
-        if HOUSE_MATCHES.iter().any(|&m| value.contains(m)) {
-            return Some(Self::House);
+    }
+}
+
+#[derive(sqlx::Type, serde::Serialize, Copy, Clone)]
+#[sqlx(type_name = "heating_type", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum HeatingType {
+    /// Own boiler ("centrala proprie"), usually on gas.
+    Central,
+    /// City heating network ("termoficare", "RADET").
+    District,
+    None,
+}
+
+const HEATING_TYPE_RULES: [Rule<HeatingType>; 3] = [
+    Rule {
+        value: HeatingType::District,
+        negated: None,
+        phrases: &["termoficare", "radet", "agent termic"],
+    },
+    Rule {
+        value: HeatingType::Central,
+        negated: Some(HeatingType::None),
+        phrases: &["centrala proprie", "centrala termica", "centrala pe gaz"],
+    },
+    Rule {
+        value: HeatingType::None,
+        negated: None,
+        phrases: &["fara incalzire", "nu are incalzire"],
+    },
+];
+
+impl HeatingType {
+    pub fn find_in_str(s: &str) -> Option<Self> {
+        Text::new(s).find(&HEATING_TYPE_RULES)
+    }
+}
+
+impl TryFrom<&str> for HeatingType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match fold(value).as_str() {
+            "central" | "central_heating" | "centrala" | "centrala proprie" | "gas" | "own" => {
+                Ok(Self::Central)
+            }
+            "district" | "urban" | "termoficare" => Ok(Self::District),
+            "none" | "fara" | "lipsa" => Ok(Self::None),
+            _ => Err(anyhow::anyhow!("Failed to parse heating type.")),
         }
+    }
+}
+
+#[derive(sqlx::Type, serde::Serialize, Copy, Clone)]
+#[sqlx(type_name = "furnishing", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Furnishing {
+    Furnished,
+    SemiFurnished,
+    Unfurnished,
+}
 
-        None
+const FURNISHING_RULES: [Rule<Furnishing>; 3] = [
+    Rule {
+        value: Furnishing::Unfurnished,
+        negated: None,
+        phrases: &["nemobilat*"],
+    },
+    Rule {
+        value: Furnishing::SemiFurnished,
+        negated: None,
+        phrases: &["semimobilat*", "semi mobilat*", "partial mobilat*"],
+    },
+    Rule {
+        value: Furnishing::Furnished,
+        negated: Some(Furnishing::Unfurnished),
+        phrases: &["mobilat*"],
+    },
+];
+
+impl Furnishing {
+    pub fn find_in_str(s: &str) -> Option<Self> {
+        Text::new(s).find(&FURNISHING_RULES)
     }
 }
 
-impl TryFrom<&str> for PropertyType {
+impl TryFrom<&str> for Furnishing {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match fold(value).as_str() {
+            "furnished" | "mobilat" | "da" | "yes" => Ok(Self::Furnished),
+            "semi_furnished" | "semimobilat" | "partial" => Ok(Self::SemiFurnished),
+            "unfurnished" | "nemobilat" | "nu" | "no" => Ok(Self::Unfurnished),
+            _ => Err(anyhow::anyhow!("Failed to parse furnishing.")),
+        }
+    }
+}
+
+#[derive(sqlx::Type, serde::Serialize, Copy, Clone)]
+#[sqlx(type_name = "parking", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Parking {
+    Garage,
+    Underground,
+    Spot,
+    None,
+}
+
+const PARKING_RULES: [Rule<Parking>; 3] = [
+    Rule {
+        value: Parking::Underground,
+        negated: None,
+        phrases: &["parcare subterana", "parcare la subsol"],
+    },
+    Rule {
+        value: Parking::Garage,
+        negated: None,
+        phrases: &["garaj*"],
+    },
+    Rule {
+        value: Parking::Spot,
+        negated: Some(Parking::None),
+        phrases: &["loc de parcare", "loc parcare", "parcare"],
+    },
+];
+
+impl Parking {
+    pub fn find_in_str(s: &str) -> Option<Self> {
+        Text::new(s).find(&PARKING_RULES)
+    }
+}
+
+impl TryFrom<&str> for Parking {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match fold(value).as_str() {
+            "garage" | "garaj" => Ok(Self::Garage),
+            "underground" | "underground_parking" | "subterana" => Ok(Self::Underground),
+            "spot" | "parking" | "parking_space" | "loc de parcare" => Ok(Self::Spot),
+            "none" | "fara" | "nu" => Ok(Self::None),
+            _ => Err(anyhow::anyhow!("Failed to parse parking.")),
+        }
+    }
+}
+
+#[derive(sqlx::Type, serde::Serialize, Copy, Clone)]
+#[sqlx(type_name = "building_material", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum BuildingMaterial {
+    Brick,
+    Concrete,
+    /// Autoclaved aerated concrete blocks.
+    Bca,
+    Wood,
+    Other,
+}
+
+const BUILDING_MATERIAL_RULES: [Rule<BuildingMaterial>; 4] = [
+    Rule {
+        value: BuildingMaterial::Brick,
+        negated: None,
+        phrases: &["caramida", "bloc din caramida"],
+    },
+    Rule {
+        value: BuildingMaterial::Bca,
+        negated: None,
+        phrases: &["bca"],
+    },
+    Rule {
+        value: BuildingMaterial::Concrete,
+        negated: None,
+        phrases: &["beton*", "panou*", "prefabricat*"],
+    },
+    Rule {
+        value: BuildingMaterial::Wood,
+        negated: None,
+        phrases: &["structura din lemn", "casa din lemn", "lemn"],
+    },
+];
+
+impl BuildingMaterial {
+    pub fn find_in_str(s: &str) -> Option<Self> {
+        Text::new(s).find(&BUILDING_MATERIAL_RULES)
+    }
+}
+
+impl TryFrom<&str> for BuildingMaterial {
     type Error = anyhow::Error;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value {
-            "apartament" | "garsoniera" => Ok(Self::Apartment),
-            "casa" => Ok(Self::House),
-            _ => Err(anyhow::anyhow!("Failed to parse property type.")),
+        match fold(value).as_str() {
+            "brick" | "caramida" => Ok(Self::Brick),
+            "concrete" | "reinforced_concrete" | "concrete_plate" | "beton" | "panouri" => {
+                Ok(Self::Concrete)
+            }
+            "bca" | "breezeblock" | "cellular_concrete" => Ok(Self::Bca),
+            "wood" | "lemn" => Ok(Self::Wood),
+            "other" | "altele" | "alt material" => Ok(Self::Other),
+            _ => Err(anyhow::anyhow!("Failed to parse building material.")),
         }
     }
 }
 
-pub struct Classified<'a, 'b> {
-    pub session: &'a Uuid,
-    pub url: &'b str,
+const ELEVATOR_RULES: [Rule<bool>; 1] = [Rule {
+    value: true,
+    negated: Some(false),
+    phrases: &["lift", "ascensor"],
+}];
+
+/// Balcony count from phrases like "2 balcoane", a lone "balcon" counts as
+/// one and "fara balcon" as none. "balcoane" without a number says there is
+/// more than one but not how many, that's no count.
+pub fn find_balcony_count_in_str(s: &str) -> Option<i16> {
+    let text = Text::new(s);
+    let words = text.words();
+    let (i, w) = words
+        .iter()
+        .enumerate()
+        .find(|(_, w)| w.starts_with("balcon") || w.starts_with("balcoane"))?;
+    if text.is_negated(i) {
+        return Some(0);
+    }
+    if w.starts_with("balcoane") {
+        return i.checked_sub(1).and_then(|i| match words[i].as_str() {
+            "doua" => Some(2),
+            "trei" => Some(3),
+            n => n.parse().ok(),
+        });
+    }
+    Some(1)
+}
+
+/// Whether the building has an elevator, as far as the text tells.
+pub fn find_elevator_in_str(s: &str) -> Option<bool> {
+    Text::new(s).find(&ELEVATOR_RULES)
+}
+
+/// A raw param/characteristic as published by the source, kept as-is so that
+/// attributes we don't model yet can still be queried from the database.
+#[derive(serde::Serialize)]
+pub struct Attribute {
+    pub key: String,
+    pub label: String,
+    pub value: String,
+}
 
-    pub orientation: Option<CardinalDirection>,
+#[derive(serde::Serialize)]
+pub struct Classified {
+    #[serde(skip)]
+    pub session: Uuid,
+    pub url: String,
+
+    pub attributes: Vec<Attribute>,
+    pub balcony_count: Option<i16>,
+    pub building_material: Option<BuildingMaterial>,
+    pub city: Option<String>,
+    pub description: String,
+    pub elevator: Option<bool>,
+    pub furnishing: Option<Furnishing>,
+    pub heating: Option<HeatingType>,
+    pub orientations: Vec<CardinalDirection>,
     pub floor: Option<i16>,
+    pub latitude: Option<f64>,
     pub layout: Option<Layout>,
+    pub longitude: Option<f64>,
     pub negotiable: bool,
+    pub parking: Option<Parking>,
     pub price: f64,
     pub currency: Currency,
-    pub property_type: PropertyType,
+    pub property_type: Option<PropertyType>,
     pub published_at: DateTime<Utc>,
     pub room_count: Option<i16>,
     pub seller_name: String,
@@ -162,3 +551,120 @@ pub struct Classified<'a, 'b> {
     pub title: String,
     pub year: Option<i32>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        find_balcony_count_in_str, find_elevator_in_str, CardinalDirection, Furnishing, Layout,
+        PropertyType,
+    };
+    use CardinalDirection::*;
+
+    #[test]
+    fn property_type_ignores_case() {
+        assert!(matches!(
+            PropertyType::find_in_str("CASA cu gradina"),
+            Some(PropertyType::House)
+        ));
+        assert!(matches!(
+            PropertyType::find_in_str("Garsonieră ultracentrală"),
+            Some(PropertyType::Studio)
+        ));
+        assert!(PropertyType::find_in_str("proaspat casatoriti").is_none());
+    }
+
+    #[test]
+    fn cardinal_direction_handles_diacritics() {
+        assert!(matches!(
+            CardinalDirection::find_in_str("expunere sudică"),
+            Some(CardinalDirection::South)
+        ));
+        assert!(matches!(
+            CardinalDirection::find_in_str("Orientare estică"),
+            Some(CardinalDirection::East)
+        ));
+    }
+
+    #[test]
+    fn property_type_prefers_specific_types() {
+        assert!(matches!(
+            PropertyType::find_in_str("Apartament duplex, 4 camere"),
+            Some(PropertyType::Duplex)
+        ));
+        assert!(matches!(
+            PropertyType::find_in_str("casa cu teren"),
+            Some(PropertyType::House)
+        ));
+        assert!(matches!(
+            PropertyType::find_in_str("Teren intravilan"),
+            Some(PropertyType::Land)
+        ));
+        assert!(PropertyType::try_from("iglu").is_err());
+    }
+
+    #[test]
+    fn property_type_prefers_homes_to_offices() {
+        assert!(matches!(
+            PropertyType::find_in_str("Apartament 2 camere zona Hala Traian"),
+            Some(PropertyType::Apartment)
+        ));
+        assert!(matches!(
+            PropertyType::find_in_str("Apartament, ideal si birou"),
+            Some(PropertyType::Apartment)
+        ));
+    }
+
+    #[test]
+    fn cardinal_direction_finds_every_exposure() {
+        assert_eq!(
+            CardinalDirection::find_all_in_str("orientare sud-est, dormitor la nord"),
+            vec![SouthEast, North]
+        );
+        assert_eq!(
+            CardinalDirection::find_all_in_str("Expunere sudică şi vestică"),
+            vec![South, West]
+        );
+    }
+
+    #[test]
+    fn cardinal_direction_is_not_the_verb_este() {
+        assert_eq!(
+            CardinalDirection::find_all_in_str("orientare la sud este luminos"),
+            vec![South]
+        );
+        assert_eq!(
+            CardinalDirection::find_all_in_str("dormitorul spre nord este racoros"),
+            vec![North]
+        );
+        assert_eq!(
+            CardinalDirection::find_all_in_str("living orientat sud estic"),
+            vec![SouthEast]
+        );
+    }
+
+    #[test]
+    fn layout_respects_word_boundaries() {
+        assert!(Layout::find_in_str("apartament nedecomandat").is_none());
+        assert!(matches!(
+            Layout::find_in_str("semi-decomandat"),
+            Some(Layout::SemiFancy)
+        ));
+        assert!(matches!(
+            Layout::find_in_str("2 camere decomandate"),
+            Some(Layout::Fancy)
+        ));
+    }
+
+    #[test]
+    fn amenities_handle_negations() {
+        assert!(matches!(
+            Furnishing::find_in_str("apartamentul nu este mobilat"),
+            Some(Furnishing::Unfurnished)
+        ));
+        assert_eq!(find_elevator_in_str("bloc fără lift"), Some(false));
+        assert_eq!(find_balcony_count_in_str("fara balcon"), Some(0));
+        assert_eq!(find_balcony_count_in_str("are 2 balcoane"), Some(2));
+        assert_eq!(find_balcony_count_in_str("balcoane inchise"), None);
+        assert_eq!(find_balcony_count_in_str("apartament cu balcoane"), None);
+    }
+}
@@ -1,12 +1,36 @@
+use std::io::{Read, Write};
+
 use anyhow::Context;
+use chrono::Utc;
+use uuid::Uuid;
 
-use crate::{config::Config, util::try_parse_session};
+use crate::{
+    config::Config,
+    page::{PageType, PageUrl},
+    util::{shutdown_signal, try_parse_session},
+};
 
-use super::extractor::{ExtractOptions, extract};
+use super::extractor::{extract, parse_page, ExtractOptions, SavedPage, EXTRACTOR_VERSION};
 
 #[derive(clap::Args)]
 pub struct ExtractCmd {
     pub session: String,
+    /// Write a new revision for pages extracted by an older extractor version.
+    #[arg(long)]
+    pub reextract: bool,
+    /// Only re-extract pages last extracted before this version (defaults to the current one).
+    #[arg(long, requires = "reextract")]
+    pub since_version: Option<i16>,
+    /// Extract pages as they are crawled, exits once the session is crawled.
+    #[arg(long, conflicts_with = "reextract")]
+    pub follow: bool,
+    /// Number of concurrent extraction workers.
+    #[arg(
+        long,
+        default_value_t = num_cpus::get(),
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub workers: usize,
 }
 
 impl ExtractCmd {
@@ -22,8 +46,17 @@ impl ExtractCmd {
 
                 let options = ExtractOptions {
                     config,
+                    follow: self.follow,
+                    reextract_below: self
+                        .reextract
+                        .then(|| self.since_version.unwrap_or(EXTRACTOR_VERSION)),
                     session,
+                    shutdown: shutdown_signal()?,
+                    workers: self.workers,
                     pool: sqlx::postgres::PgPoolOptions::new()
+                        // Every worker holds a connection while extracting, plus
+                        // the session checks and the pages listener.
+                        .max_connections(self.workers as u32 + 2)
                         .acquire_timeout(std::time::Duration::from_secs(2))
                         .connect_lazy(config.database_url.as_ref())
                         .context("Failed to establish lazy connection to postgres.")?,
@@ -33,3 +66,120 @@ impl ExtractCmd {
             })
     }
 }
+
+/// Runs a parser over a local HTML file and prints what it extracted as JSON,
+/// no database needed.
+#[derive(clap::Args)]
+pub struct ExtractFileCmd {
+    /// HTML file to parse, `-` reads from stdin.
+    pub path: String,
+    /// Page type of the file, guessed from `--url` when missing.
+    #[arg(long = "type", value_enum)]
+    pub page_type: Option<PageType>,
+    /// URL the page was fetched from, defaults to the path.
+    #[arg(long)]
+    pub url: Option<String>,
+}
+
+impl ExtractFileCmd {
+    pub fn work(&self) -> anyhow::Result<()> {
+        self.write_extraction(std::io::stdout())
+    }
+
+    /// Parses the file and writes the extraction to `out` as JSON.
+    pub fn write_extraction<W: Write>(&self, mut out: W) -> anyhow::Result<()> {
+        let page_type = match (self.page_type, &self.url) {
+            (Some(page_type), _) => page_type,
+            (None, Some(url)) => PageType::from(&PageUrl::parse(url)?),
+            (None, None) => anyhow::bail!("Cannot tell the page type, pass --type or --url."),
+        };
+
+        let mut content = String::new();
+        match self.path.as_str() {
+            "-" => std::io::stdin()
+                .read_to_string(&mut content)
+                .context("Failed to read the page from stdin.")?,
+            path => std::fs::File::open(path)
+                .and_then(|mut f| f.read_to_string(&mut content))
+                .with_context(|| format!("Failed to read the page from {}.", path))?,
+        };
+
+        let page = SavedPage {
+            content,
+            crawled_at: Utc::now(),
+            page_type,
+            url: self.url.clone().unwrap_or_else(|| self.path.clone()),
+        };
+        let session = Uuid::nil();
+        let extraction = parse_page(&session, &page)?;
+
+        serde_json::to_writer_pretty(&mut out, &extraction)
+            .context("Failed to serialize the extraction.")?;
+        writeln!(out)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ExtractFileCmd;
+    use crate::page::PageType;
+
+    fn extract_file(
+        path: &str,
+        page_type: Option<PageType>,
+        url: Option<&str>,
+    ) -> anyhow::Result<serde_json::Value> {
+        let cmd = ExtractFileCmd {
+            path: path.into(),
+            page_type,
+            url: url.map(String::from),
+        };
+        let mut out = Vec::new();
+        cmd.write_extraction(&mut out)?;
+        Ok(serde_json::from_slice(&out).unwrap())
+    }
+
+    #[test]
+    fn extracts_olx_files() {
+        let json = extract_file(
+            "src/extract/test_assets/olx-item.html",
+            Some(PageType::OlxItem),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            json["classified"]["url"],
+            "src/extract/test_assets/olx-item.html"
+        );
+        assert!(json["classified"]["title"].is_string());
+        assert!(json["issues"].is_array());
+    }
+
+    #[test]
+    fn guesses_the_page_type_from_the_url() {
+        let url =
+            "https://www.storia.ro/ro/oferta/inchiriere-garsoniera-lux-urban-plaza-IDtVQ3.html";
+        let json =
+            extract_file("src/extract/test_assets/storia-item.html", None, Some(url)).unwrap();
+
+        assert_eq!(json["classified"]["url"], url);
+        assert!(json["classified"]["title"].is_string());
+    }
+
+    #[test]
+    fn unknown_page_types_are_errors() {
+        let file = "src/extract/test_assets/olx-item.html";
+
+        let e = extract_file(file, None, None).unwrap_err();
+        assert!(e.to_string().contains("Cannot tell the page type"));
+
+        let e = extract_file(file, None, Some("https://example.com/item.html")).unwrap_err();
+        assert!(e.to_string().contains("Don't know how to handle"));
+
+        let e = extract_file(file, Some(PageType::OlxList), None).unwrap_err();
+        assert!(e.to_string().contains("List pages have no classified"));
+    }
+}
@@ -0,0 +1,100 @@
+use super::classified::Classified;
+
+#[derive(sqlx::Type, serde::Serialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[sqlx(type_name = "extraction_issue_severity", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// A value was there but couldn't be mapped, a fallback might have been used.
+    Warning,
+    /// The field could not be extracted and is left empty.
+    Error,
+    /// Nothing could be extracted from the page.
+    Fatal,
+}
+
+#[derive(serde::Serialize)]
+pub struct Issue {
+    /// Column of the field, `None` for issues about the whole page.
+    pub field: Option<&'static str>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Issue {
+    pub fn fatal(e: &anyhow::Error) -> Self {
+        Self {
+            field: None,
+            severity: Severity::Fatal,
+            message: format!("{:#}", e),
+        }
+    }
+}
+
+/// Collects field level issues while a classified is being parsed, so one bad
+/// field doesn't throw away the rest of the page.
+#[derive(Default)]
+pub struct Diagnostics {
+    pub issues: Vec<Issue>,
+}
+
+impl Diagnostics {
+    fn record<T>(
+        &mut self,
+        field: &'static str,
+        severity: Severity,
+        result: anyhow::Result<Option<T>>,
+    ) -> Option<T> {
+        result.unwrap_or_else(|e| {
+            self.issues.push(Issue {
+                field: Some(field),
+                severity,
+                message: format!("{:#}", e),
+            });
+            None
+        })
+    }
+
+    /// Unwraps a parsed field, a failure is recorded as a warning.
+    pub fn warning<T>(
+        &mut self,
+        field: &'static str,
+        result: anyhow::Result<Option<T>>,
+    ) -> Option<T> {
+        self.record(field, Severity::Warning, result)
+    }
+
+    /// Unwraps a parsed field, a failure is recorded as an error.
+    pub fn error<T>(
+        &mut self,
+        field: &'static str,
+        result: anyhow::Result<Option<T>>,
+    ) -> Option<T> {
+        self.record(field, Severity::Error, result)
+    }
+}
+
+/// A classified, possibly missing some fields, and what went wrong with them.
+#[derive(serde::Serialize)]
+pub struct Extraction {
+    pub classified: Classified,
+    pub issues: Vec<Issue>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Diagnostics, Severity};
+
+    #[test]
+    fn failed_fields_are_recorded() {
+        let mut d = Diagnostics::default();
+
+        let floor: Option<i16> = d.error("floor", "etaj".parse().map(Some).map_err(Into::into));
+        let rooms: Option<i16> = d.warning("room_count", Ok(Some(2)));
+
+        assert_eq!(floor, None);
+        assert_eq!(rooms, Some(2));
+        assert_eq!(d.issues.len(), 1);
+        assert_eq!(d.issues[0].field, Some("floor"));
+        assert_eq!(d.issues[0].severity, Severity::Error);
+    }
+}
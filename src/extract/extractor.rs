@@ -1,12 +1,29 @@
 use anyhow::Context;
 use chrono::{DateTime, Utc};
-use futures::StreamExt;
-use sqlx::PgPool;
+use sqlx::{postgres::PgListener, PgPool};
+use std::sync::Arc;
+use tokio::sync::Notify;
 use uuid::Uuid;
 
-use crate::{config::Config, extract::olx, extract::storia, page::PageType, session::Session};
+use crate::{
+    ads::refresh_classified_changes,
+    config::Config,
+    extract::olx,
+    extract::storia,
+    page::{PageType, PAGES_SAVED_CHANNEL},
+    session::{transition, Session, SessionStatus},
+    util::{Currency, PgTransaction, Shutdown},
+};
 
-use super::classified::{CardinalDirection, Classified, Layout, PropertyType, SellerType};
+use super::classified::{
+    BuildingMaterial, CardinalDirection, Classified, Furnishing, HeatingType, Layout, Parking,
+    PropertyType, SellerType,
+};
+use super::diagnostics::{Extraction, Issue, Severity};
+
+/// Written along every extraction, bump it whenever a parser change is worth
+/// re-running over old sessions (`extract --reextract`).
+pub const EXTRACTOR_VERSION: i16 = 7;
 
 pub struct SavedPage {
     pub content: String,
@@ -15,10 +32,20 @@ pub struct SavedPage {
     pub url: String,
 }
 
+/// How long a following worker waits for a notification before looking for
+/// new pages anyway.
+const FOLLOW_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
 pub struct ExtractOptions<'a> {
     pub config: &'a Config,
+    /// Keep extracting while the session is being crawled.
+    pub follow: bool,
     pub pool: PgPool,
+    /// Re-extract pages last extracted by a version older than this one.
+    pub reextract_below: Option<i16>,
     pub session: uuid::Uuid,
+    pub shutdown: Shutdown,
+    pub workers: usize,
 }
 
 pub async fn extract<'a>(options: &'a ExtractOptions<'a>) -> anyhow::Result<()> {
@@ -26,72 +53,353 @@ pub async fn extract<'a>(options: &'a ExtractOptions<'a>) -> anyhow::Result<()>
         .await?
         .ok_or_else(|| anyhow::anyhow!("No session found in DB."))?;
 
-    tracing::info!("Session loaded from database ({:?}).", session.crawled_at);
+    tracing::info!("Session loaded from database ({}).", session.status);
+
+    let below_version = match options.reextract_below {
+        Some(version) if version > EXTRACTOR_VERSION => {
+            return Err(anyhow::anyhow!(
+                "Cannot re-extract below version {}, the current version is {}.",
+                version,
+                EXTRACTOR_VERSION
+            ));
+        }
+        Some(version) => {
+            tracing::info!("Re-extracting pages extracted before version {}.", version);
+            version
+        }
+        None => 1,
+    };
+
+    let new_pages = match (session.status, options.follow) {
+        (SessionStatus::Created | SessionStatus::Crawling, false) => {
+            return Err(anyhow::anyhow!(
+                "Session did not finish crawling, use --follow to extract while crawling."
+            ));
+        }
+        (SessionStatus::Created | SessionStatus::Crawling, true) => {
+            let mut listener = PgListener::connect_with(&options.pool)
+                .await
+                .context("Failed to connect the pages listener.")?;
+            listener
+                .listen(PAGES_SAVED_CHANNEL)
+                .await
+                .context("Failed to listen for saved pages.")?;
+
+            let new_pages = Arc::new(Notify::new());
+            tokio::spawn(listen_for_pages(
+                listener,
+                session.session,
+                new_pages.clone(),
+            ));
+            tracing::info!("Following session while it's being crawled.");
+            Some(new_pages)
+        }
+        _ => {
+            let mut transaction = options.pool.begin().await?;
+            transition(
+                &mut transaction,
+                &session.session,
+                SessionStatus::Extracting,
+                None,
+            )
+            .await?;
+            transaction.commit().await?;
+            None
+        }
+    };
+
+    let workers = (0..options.workers).map(|c| {
+        tracing::info!("Spawning worker {}.", c);
+        tokio::spawn(spawn_worker(
+            options.pool.clone(),
+            session.session,
+            below_version,
+            new_pages.clone(),
+            options.shutdown.clone(),
+        ))
+    });
 
-    if session.crawled_at.is_none() {
+    let failure = futures::future::join_all(workers)
+        .await
+        .into_iter()
+        .find_map(|worker| match worker {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(e),
+            Err(e) => Some(anyhow::Error::new(e).context("Extraction worker panicked.")),
+        });
+
+    if let Some(e) = failure {
+        // Same as when interrupted, a followed session is left to its crawler.
+        if new_pages.is_none() {
+            let mut transaction = options.pool.begin().await?;
+            transition(
+                &mut transaction,
+                &session.session,
+                SessionStatus::Failed,
+                Some(&format!("{:#}", e)),
+            )
+            .await?;
+            transaction.commit().await?;
+        }
+        return Err(e);
+    }
+
+    if let Some(signal) = *options.shutdown.borrow() {
+        // A followed session belongs to its crawler, only ours gets aborted.
+        if new_pages.is_none() {
+            let mut transaction = options.pool.begin().await?;
+            transition(
+                &mut transaction,
+                &session.session,
+                SessionStatus::Aborted,
+                Some(&format!("Interrupted by {}.", signal)),
+            )
+            .await?;
+            transaction.commit().await?;
+        }
         return Err(anyhow::anyhow!(
-            "Session did not finish crawling, currently not supported."
+            "Extraction interrupted, resume it with `extract {}`.",
+            session.session
         ));
     }
 
-    let workers = futures::stream::iter((0..4).into_iter().map(|c| {
-        tracing::info!("Spawning worker {}.", c);
-        tokio::spawn(spawn_worker(options.pool.clone(), session.session))
-    }))
-    .buffer_unordered(3)
-    .collect::<Vec<_>>();
+    let mut transaction = options.pool.begin().await?;
+    transition(
+        &mut transaction,
+        &session.session,
+        SessionStatus::Extracted,
+        None,
+    )
+    .await?;
+    transaction.commit().await?;
 
-    workers.await;
+    refresh_classified_changes(&options.pool)
+        .await
+        .context("Failed to refresh the classified changes.")?;
 
     Ok(())
 }
 
-async fn spawn_worker(pool: PgPool, session: Uuid) {
+/// Wakes the workers up whenever a page of `session` gets saved.
+async fn listen_for_pages(mut listener: PgListener, session: Uuid, new_pages: Arc<Notify>) {
+    let session = session.to_string();
+    loop {
+        match listener.recv().await {
+            Ok(notification) if notification.payload() == session => new_pages.notify_waiters(),
+            Ok(_) => {}
+            Err(e) => {
+                tracing::error!("Pages listener failed, falling back to polling ({:?})", e);
+                return;
+            }
+        }
+    }
+}
+
+async fn spawn_worker(
+    pool: PgPool,
+    session: Uuid,
+    below_version: i16,
+    new_pages: Option<Arc<Notify>>,
+    mut shutdown: Shutdown,
+) -> anyhow::Result<()> {
     let sleepy = std::time::Duration::from_secs(1);
+    // Pages saved before the session got marked as crawled are visible by the
+    // time we see it crawled, so one more empty pass means we're done.
+    let mut crawled = new_pages.is_none();
 
     loop {
-        match load_saved_page(&pool, &session).await {
-            Ok(Some(page)) => {
-                tracing::info!("Extracting {}", &page.url);
-                let classified_result = match page.page_type {
-                    PageType::OlxList => todo!(),
-                    PageType::OlxItem => olx::parse_classified(&session, &page),
-                    PageType::StoriaItem => storia::parse_classified(&session, &page),
-                };
-
-                match classified_result {
-                    Ok(classified) => {
-                        if let Err(e) = save_classified(&pool, &classified).await {
-                            tracing::error!("Failed saving classified: {:?}", e);
-                        }
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed parsing classified: {:?}", e);
-                    }
-                }
-            }
-            Ok(None) => {
+        // The page being extracted is saved first, it only takes a moment.
+        if shutdown.borrow().is_some() {
+            tracing::info!("Stopped claiming pages.");
+            break;
+        }
+        match extract_next_page(&pool, &session, below_version).await {
+            Ok(true) => {}
+            Ok(false) if crawled => {
                 tracing::info!("No more pages to extract, breaking...");
                 break;
             }
+            Ok(false) => match load_session(&pool, &session).await {
+                Ok(Some(s)) if s.crawled_at.is_some() => {
+                    tracing::info!("Session crawled, last pass...");
+                    crawled = true;
+                }
+                // Never crawled, nothing more is coming until it's resumed.
+                Ok(Some(s))
+                    if matches!(s.status, SessionStatus::Failed | SessionStatus::Aborted) =>
+                {
+                    return Err(anyhow::anyhow!(
+                        "The followed crawl is {}, resume it before extracting.",
+                        s.status
+                    ));
+                }
+                Ok(_) => {
+                    tracing::info!("Waiting for new pages...");
+                    if let Some(new_pages) = &new_pages {
+                        tokio::select! {
+                            _ = tokio::time::timeout(FOLLOW_POLL_INTERVAL, new_pages.notified()) => {},
+                            Ok(()) = shutdown.changed() => {},
+                        };
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("Failed to check session ({:?})", e);
+                    return Err(e);
+                }
+            },
             Err(sqlx::Error::PoolTimedOut) => {
                 tracing::warn!("Pool timed out, pausing a bit...");
-                std::thread::sleep(sleepy);
+                tokio::time::sleep(sleepy).await;
             }
             Err(e) => {
                 tracing::error!("Failed to retrieve page ({:?})", e);
-                return;
+                return Err(anyhow::Error::new(e).context("Failed to extract the next page."));
             }
         };
         tracing::info!("Loop");
     }
     tracing::info!("Finished working");
+    Ok(())
+}
+
+/// Runs the parser matching the page type.
+pub fn parse_page(session: &Uuid, page: &SavedPage) -> anyhow::Result<Extraction> {
+    match page.page_type {
+        PageType::OlxList => Err(anyhow::anyhow!("List pages have no classified.")),
+        PageType::OlxItem => olx::parse_classified(session, page),
+        PageType::StoriaItem => storia::parse_classified(session, page),
+    }
+}
+
+/// Claims a page, parses it and saves the result in a single transaction, the
+/// page stays locked for the other workers until it's committed. Returns
+/// whether there was a page to extract.
+#[tracing::instrument(skip(pool))]
+async fn extract_next_page(pool: &PgPool, session: &Uuid, below_version: i16) -> sqlx::Result<bool> {
+    let mut transaction = pool.begin().await?;
+    let page = match load_saved_page(&mut transaction, session, below_version).await? {
+        Some(page) => page,
+        None => {
+            transaction.rollback().await?;
+            return Ok(false);
+        }
+    };
+
+    tracing::info!("Extracting {}", &page.url);
+    let url = page.url.clone();
+    let page_session = *session;
+    // HTML parsing is CPU heavy, keep it off the async workers.
+    let extraction = tokio::task::spawn_blocking(move || parse_page(&page_session, &page))
+        .await
+        .unwrap_or_else(|e| Err(anyhow::Error::new(e).context("Parser panicked.")));
+
+    match save_extraction(&mut transaction, session, &url, &extraction).await {
+        Ok(_) => transaction.commit().await?,
+        Err(e) => {
+            tracing::error!("Failed saving extraction: {:?}", e);
+            transaction.rollback().await?;
+
+            // Otherwise the page would be claimed again right away.
+            let mut transaction = pool.begin().await?;
+            let failure = Err(anyhow::Error::new(e).context("Failed saving extraction."));
+            save_extraction(&mut transaction, session, &url, &failure).await?;
+            transaction.commit().await?;
+        }
+    };
+
+    Ok(true)
+}
+
+/// Saves the classified and its issues, or marks the page as failed when
+/// nothing could be extracted so it's not picked up again.
+#[tracing::instrument(skip_all)]
+async fn save_extraction<'a>(
+    transaction: &mut PgTransaction<'a>,
+    session: &Uuid,
+    url: &str,
+    extraction: &anyhow::Result<Extraction>,
+) -> sqlx::Result<()> {
+    let revision = next_revision(transaction, session, url).await?;
+    match extraction {
+        Ok(extraction) => {
+            if !extraction.issues.is_empty() {
+                tracing::warn!("Extracted with {} issue(s).", extraction.issues.len());
+            }
+            save_classified(transaction, &extraction.classified, revision).await?;
+            save_issues(transaction, session, url, revision, &extraction.issues).await?;
+        }
+        Err(e) => {
+            tracing::error!("Failed parsing classified: {:?}", e);
+            save_issues(transaction, session, url, revision, &[Issue::fatal(e)]).await?;
+        }
+    };
+    Ok(())
+}
+
+/// Revisions are counted per page, over both classifieds and failed attempts.
+async fn next_revision<'a>(
+    transaction: &mut PgTransaction<'a>,
+    session: &Uuid,
+    url: &str,
+) -> sqlx::Result<i16> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT
+            (COALESCE(GREATEST(
+                (SELECT MAX(revision) FROM classifieds WHERE session=$1 AND url=$2),
+                (SELECT MAX(revision) FROM extraction_issues WHERE session=$1 AND url=$2)
+            ), 0) + 1)::SMALLINT AS "revision!"
+        "#,
+        session,
+        url,
+    )
+    .fetch_one(transaction)
+    .await
 }
 
 #[tracing::instrument(skip_all)]
-async fn save_classified<'a, 'b>(
-    pool: &PgPool,
-    classified: &Classified<'a, 'b>,
+async fn save_issues<'a>(
+    transaction: &mut PgTransaction<'a>,
+    session: &Uuid,
+    url: &str,
+    revision: i16,
+    issues: &[Issue],
+) -> sqlx::Result<()> {
+    for issue in issues {
+        sqlx::query!(
+            r#"
+            INSERT INTO extraction_issues
+            (
+                session,
+                url,
+                revision,
+                extractor_version,
+                field,
+                severity,
+                message,
+                created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, CURRENT_TIMESTAMP);
+            "#,
+            session,
+            url,
+            revision,
+            EXTRACTOR_VERSION,
+            issue.field,
+            issue.severity as Severity,
+            &issue.message,
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn save_classified<'a>(
+    transaction: &mut PgTransaction<'a>,
+    classified: &Classified,
+    revision: i16,
 ) -> sqlx::Result<()> {
     sqlx::query!(
         r#"
@@ -100,12 +408,21 @@ async fn save_classified<'a, 'b>(
             session,
             url,
             revision,
+            extractor_version,
             extracted_at,
 
-            orientation,
+            attributes,
+            balcony_count,
+            building_material,
+            description,
+            elevator,
+            furnishing,
+            heating,
+            orientations,
             floor,
             layout,
             negotiable,
+            parking,
             price,
             property_type,
             published_at,
@@ -114,16 +431,19 @@ async fn save_classified<'a, 'b>(
             seller_type,
             surface,
             title,
-            year
+            year,
+            currency,
+            city,
+            latitude,
+            longitude
         )
         VALUES (
             $1,
             $2,
-            1,
-            CURRENT_TIMESTAMP,
-
             $3,
             $4,
+            CURRENT_TIMESTAMP,
+
             $5,
             $6,
             $7,
@@ -134,26 +454,54 @@ async fn save_classified<'a, 'b>(
             $12,
             $13,
             $14,
-            $15
+            $15,
+            $16,
+            $17,
+            $18,
+            $19,
+            $20,
+            $21,
+            $22,
+            $23,
+            $24,
+            $25,
+            $26,
+            $27,
+            $28,
+            $29
         );
         "#,
         classified.session,
         &classified.url,
-        classified.orientation as Option<CardinalDirection>,
+        revision,
+        EXTRACTOR_VERSION,
+        sqlx::types::Json(&classified.attributes) as _,
+        classified.balcony_count,
+        classified.building_material as Option<BuildingMaterial>,
+        &classified.description,
+        classified.elevator,
+        classified.furnishing as Option<Furnishing>,
+        classified.heating as Option<HeatingType>,
+        &classified.orientations as &[CardinalDirection],
         classified.floor,
         classified.layout as Option<Layout>,
         &classified.negotiable,
+        classified.parking as Option<Parking>,
         classified.price,
-        classified.property_type as PropertyType,
+        classified.property_type as Option<PropertyType>,
         &classified.published_at,
         classified.room_count,
         &classified.seller_name,
         classified.seller_type as SellerType,
         classified.surface,
         &classified.title,
-        classified.year
+        classified.year,
+        classified.currency as Currency,
+        classified.city,
+        classified.latitude,
+        classified.longitude
     )
-    .execute(pool)
+    .execute(transaction)
     .await
     .map(|_| ())
 }
@@ -165,7 +513,8 @@ async fn load_session(pool: &PgPool, session: &Uuid) -> anyhow::Result<Option<Se
         SELECT
           session,
           created_at,
-          crawled_at
+          crawled_at,
+          status AS "status: SessionStatus"
         FROM sessions
         WHERE session=$1
         "#,
@@ -176,30 +525,47 @@ async fn load_session(pool: &PgPool, session: &Uuid) -> anyhow::Result<Option<Se
     .context("Failed retrieving session.")
 }
 
-async fn load_saved_page(pool: &PgPool, session: &Uuid) -> Result<Option<SavedPage>, sqlx::Error> {
+/// Next page without any extraction attempt from `below_version` or newer.
+/// With `below_version` at 1 that's only the pages never extracted.
+async fn load_saved_page<'a>(
+    transaction: &mut PgTransaction<'a>,
+    session: &Uuid,
+    below_version: i16,
+) -> Result<Option<SavedPage>, sqlx::Error> {
     sqlx::query_as!(
         SavedPage,
         r#"
         SELECT
-            p.content,
+            p.content AS "content!",
             p.crawled_at,
             p.page_type as "page_type: _",
             p.url
         FROM pages AS p
         WHERE session=$1
         AND page_type IN ('olx_item', 'storia_item')
-            AND NOT EXISTS (
-                SELECT session
-                FROM classifieds AS c
-                WHERE c.session=p.session
-                    AND c.url=p.url
-            )
+        AND content IS NOT NULL
+            AND COALESCE(GREATEST(
+                (
+                    SELECT MAX(c.extractor_version)
+                    FROM classifieds AS c
+                    WHERE c.session=p.session
+                        AND c.url=p.url
+                ),
+                (
+                    SELECT MAX(i.extractor_version)
+                    FROM extraction_issues AS i
+                    WHERE i.session=p.session
+                        AND i.url=p.url
+                        AND i.severity='fatal'
+                )
+            ), 0)::SMALLINT < $2
         FOR UPDATE
         SKIP LOCKED
         LIMIT 1
         "#,
-        session
+        session,
+        below_version,
     )
-    .fetch_optional(pool)
+    .fetch_optional(transaction)
     .await
 }
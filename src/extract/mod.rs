@@ -1,5 +1,7 @@
 pub mod classified;
 pub mod extractor;
 pub mod command;
+pub mod diagnostics;
 pub mod olx;
 pub mod storia;
+pub mod text;
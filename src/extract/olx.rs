@@ -6,23 +6,34 @@ use chrono::{DateTime, Utc};
 use crate::util::Currency;
 
 use super::{
-    classified::{Classified, PropertyType, SellerType},
+    classified::{
+        find_balcony_count_in_str, find_elevator_in_str, Attribute, BuildingMaterial,
+        CardinalDirection, Classified, Furnishing, HeatingType, Parking, PropertyType, SellerType,
+    },
+    diagnostics::{Diagnostics, Extraction},
     extractor::SavedPage,
+    text::html_text,
 };
 
-// #[derive(serde::Deserialize)]
-// #[serde(rename_all = "camelCase")]
-// struct OlxClassifiedLocation {
-//     city_name: String,
-//     region_name: String,
-// }
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OlxClassifiedLocation {
+    city_name: String,
+    // region_name: String,
+}
+
+#[derive(serde::Deserialize)]
+struct OlxClassifiedMap {
+    lat: f64,
+    lon: f64,
+}
 
 #[derive(serde::Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct OlxClassifiedParam {
     key: String,
-    // name: String,
-    // normalized_value: String,
+    name: String,
+    normalized_value: String,
     value: String,
 }
 
@@ -59,7 +70,8 @@ struct OlxClassified {
     // is_highlighted: bool,
     // is_promoted: bool,
     // last_refresh_time: DateTime<Utc>,
-    // location: OlxClassifiedLocation,
+    location: OlxClassifiedLocation,
+    map: Option<OlxClassifiedMap>,
     params: Vec<OlxClassifiedParam>,
     // https://frankfurt.apollo.olxcdn.com:443/v1/files/2i2w3927ow9i3-RO/image;s=429x537"
     // photos: Vec<String>,
@@ -115,38 +127,121 @@ pub fn extract_page_json(page: &SavedPage) -> anyhow::Result<String> {
         .ok_or_else(|| anyhow::anyhow!("Failed to unescape Javascript JSON string."))
 }
 
-pub fn parse_classified<'a, 'b>(
-    session: &'a uuid::Uuid,
-    page: &'b SavedPage,
-) -> anyhow::Result<Classified<'a, 'b>> {
+/// Normalized value of the first param matching one of `keys`.
+fn find_param<'a>(params: &'a [OlxClassifiedParam], keys: &[&str]) -> Option<&'a str> {
+    params
+        .iter()
+        .find(|p| keys.contains(&p.key.as_str()))
+        .map(|p| p.normalized_value.as_str())
+}
+
+fn parse_yes_no(value: &str) -> anyhow::Result<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "da" | "yes" | "1" | "true" => Ok(true),
+        "nu" | "no" | "0" | "false" => Ok(false),
+        _ => Err(anyhow!("Failed to parse yes/no value.")),
+    }
+}
+
+pub fn parse_classified(session: &uuid::Uuid, page: &SavedPage) -> anyhow::Result<Extraction> {
     let json = extract_page_json(page).context("Failed extracting OLX JSON from page.")?;
 
     let olx_classified_wrapper: OlxClassifiedWrapper =
         serde_json::from_str(json.as_str()).context("Failed parsing OLX JSON.")?;
 
     let o = olx_classified_wrapper.ad.ad;
+    let mut d = Diagnostics::default();
+    let description = html_text(&o.description);
 
-    Ok(Classified {
-        session,
-        url: &page.url,
-        orientation: o.params.iter().find_map(|_| None),
-        floor: o
+    let classified = Classified {
+        session: *session,
+        url: page.url.clone(),
+        attributes: o
             .params
             .iter()
-            .find_map(|p| match p.key == "floor" {
-                true => Some(p.value.as_str()),
-                _ => None,
+            .map(|p| Attribute {
+                key: p.key.clone(),
+                label: p.name.clone(),
+                value: p.normalized_value.clone(),
             })
-            .map_or(Ok(None), |v| match v {
-                "parter" | "Parter" => Ok(Some(0)),
-                v => v.parse().map(Some).context("Failed parsing OLX floor"),
-            })?,
+            .collect(),
+        balcony_count: d
+            .warning(
+                "balcony_count",
+                find_param(&o.params, &["balconies", "balcoane"])
+                    .map(|v| v.parse().context("Failed parsing OLX balcony count."))
+                    .transpose(),
+            )
+            .or_else(|| find_balcony_count_in_str(&description)),
+        building_material: d
+            .warning(
+                "building_material",
+                find_param(&o.params, &["building_material", "material"])
+                    .map(BuildingMaterial::try_from)
+                    .transpose(),
+            )
+            .or_else(|| BuildingMaterial::find_in_str(&description)),
+        city: Some(o.location.city_name),
+        description: description.clone(),
+        elevator: d
+            .warning(
+                "elevator",
+                find_param(&o.params, &["elevator", "lift"])
+                    .map(parse_yes_no)
+                    .transpose(),
+            )
+            .or_else(|| find_elevator_in_str(&description)),
+        furnishing: d
+            .warning(
+                "furnishing",
+                find_param(&o.params, &["furnished", "mobilat"])
+                    .map(Furnishing::try_from)
+                    .transpose(),
+            )
+            .or_else(|| Furnishing::find_in_str(&description)),
+        heating: d
+            .warning(
+                "heating",
+                find_param(&o.params, &["heating", "incalzire"])
+                    .map(HeatingType::try_from)
+                    .transpose(),
+            )
+            .or_else(|| HeatingType::find_in_str(&description)),
+        orientations: CardinalDirection::find_all_in_str(&description),
+        floor: d.error(
+            "floor",
+            o.params
+                .iter()
+                .find_map(|p| match p.key == "floor" {
+                    true => Some(p.value.as_str()),
+                    _ => None,
+                })
+                .map_or(Ok(None), |v| match v {
+                    "parter" | "Parter" => Ok(Some(0)),
+                    v => v.parse().map(Some).context("Failed parsing OLX floor"),
+                }),
+        ),
+        latitude: o.map.as_ref().map(|m| m.lat),
         layout: o.params.iter().find_map(|_| None),
+        longitude: o.map.as_ref().map(|m| m.lon),
         negotiable: o.price.regular_price.negotiable,
+        parking: d
+            .warning(
+                "parking",
+                find_param(&o.params, &["parking", "parcare"])
+                    .map(Parking::try_from)
+                    .transpose(),
+            )
+            .or_else(|| Parking::find_in_str(&description)),
         price: o.price.regular_price.value,
         currency: o.price.regular_price.currency_code,
-        property_type: PropertyType::find_in_str(o.description.as_str())
-            .unwrap_or(PropertyType::Apartment),
+        property_type: d.error(
+            "property_type",
+            PropertyType::find_in_str(&o.title)
+                .or_else(|| PropertyType::find_in_str(&description))
+                .ok_or_else(|| anyhow!("Failed to find OLX property type."))
+                .map(Some),
+        ),
         published_at: o.created_time,
         room_count: None, // TODO:
         seller_name: o.user.name,
@@ -154,19 +249,44 @@ pub fn parse_classified<'a, 'b>(
             true => SellerType::Private,
             _ => SellerType::Company,
         },
-        surface: None,
+        surface: d.warning(
+            "surface",
+            find_param(&o.params, &["m"])
+                .map(|v| {
+                    v.parse::<f64>()
+                        .map(|v| v.round() as i32)
+                        .context("Failed parsing OLX surface.")
+                })
+                .transpose(),
+        ),
         title: o.title,
         year: None,
+    };
+
+    Ok(Extraction {
+        classified,
+        issues: d.issues,
     })
 }
 
 #[cfg(test)]
 mod tests {
     use super::{
-        super::extractor::SavedPage, extract_page_json, parse_classified, OlxClassifiedWrapper,
+        super::diagnostics::{Issue, Severity},
+        super::extractor::SavedPage,
+        extract_page_json, parse_classified, OlxClassifiedWrapper,
     };
     use crate::page::PageType;
 
+    fn saved_page(file: &str) -> SavedPage {
+        SavedPage {
+            url: "https://www.olx.ro/d/oferta/garsoniera-uzina-2-IDgC0Kq.html".into(),
+            content: std::fs::read_to_string(file).unwrap(),
+            page_type: PageType::OlxItem,
+            crawled_at: chrono::offset::Utc::now(),
+        }
+    }
+
     #[test]
     fn expected_json_fields() {
         let page = SavedPage {
@@ -201,4 +321,46 @@ mod tests {
             panic!("{:?}", e);
         }
     }
+
+    #[test]
+    fn malformed_fields_are_reported() {
+        let page = saved_page("src/extract/test_assets/olx-item-malformed.html");
+
+        let extraction = parse_classified(&uuid::Uuid::new_v4(), &page).unwrap();
+
+        let issues: Vec<(Option<&str>, Severity)> = extraction
+            .issues
+            .iter()
+            .map(|i| (i.field, i.severity))
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                (Some("floor"), Severity::Error),
+                (Some("surface"), Severity::Warning)
+            ]
+        );
+        assert_eq!(extraction.classified.floor, None);
+        assert_eq!(extraction.classified.surface, None);
+        assert_eq!(extraction.classified.title, "garsoniera Uzina 2");
+    }
+
+    #[test]
+    fn missing_required_fields_are_fatal() {
+        let page = saved_page("src/extract/test_assets/olx-item-missing-price.html");
+
+        let e = match parse_classified(&uuid::Uuid::new_v4(), &page) {
+            Ok(_) => panic!("Parsed a classified without a price."),
+            Err(e) => e,
+        };
+
+        let issue = Issue::fatal(&e);
+        assert_eq!(issue.field, None);
+        assert_eq!(issue.severity, Severity::Fatal);
+        assert!(
+            issue.message.contains("missing field `price`"),
+            "{}",
+            issue.message
+        );
+    }
 }
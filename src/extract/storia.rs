@@ -4,14 +4,22 @@ use chrono::{DateTime, Utc};
 use crate::util::Currency;
 
 use super::{
-    classified::{CardinalDirection, Classified, Layout, PropertyType, SellerType},
+    classified::{
+        find_balcony_count_in_str, find_elevator_in_str, Attribute, BuildingMaterial,
+        CardinalDirection, Classified, Furnishing, HeatingType, Layout, Parking, PropertyType,
+        SellerType,
+    },
+    diagnostics::{Diagnostics, Extraction},
     extractor::SavedPage,
+    text::html_text,
 };
 
 #[derive(serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct Info {
-    label: String,
+    /// The field key (`commodities`), Storia calls it a label.
+    #[serde(rename = "label")]
+    key: String,
     values: Vec<String>,
 }
 
@@ -36,26 +44,47 @@ struct Characteristic {
 
 #[derive(serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct Owner {
+struct Coordinates {
+    latitude: f64,
+    longitude: f64,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct City {
     name: String,
 }
 
 #[derive(serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct Agency {}
+struct Address {
+    city: Option<City>,
+}
 
 #[derive(serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct Breadcrumb {}
+struct Location {
+    address: Address,
+    coordinates: Option<Coordinates>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Owner {
+    name: String,
+}
 
 #[derive(serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct StoriaClassified {
+    additional_information: Vec<Info>,
     created_at: DateTime<Utc>,
+    description: String,
     title: String,
-    top_information: Vec<Info>,
+    // top_information: Vec<Info>,
     characteristics: Vec<Characteristic>,
     // images: Vec<Image>,
+    location: Location,
     owner: Owner,
 }
 
@@ -92,16 +121,72 @@ pub fn extract_page_json(page: &SavedPage) -> anyhow::Result<String> {
         .map(|s| s.to_string())
 }
 
-pub fn parse_classified<'a, 'b>(
-    session: &'a uuid::Uuid,
-    page: &'b SavedPage,
-) -> anyhow::Result<Classified<'a, 'b>> {
+/// Characteristics are single valued, additional information entries are
+/// multi valued (`commodity::furnished`), those get one attribute per value.
+/// Additional information has no label of its own, the key stands in for it.
+fn attributes(o: &StoriaClassified) -> Vec<Attribute> {
+    let characteristics = o.characteristics.iter().map(|c| Attribute {
+        key: c.key.clone(),
+        label: c.label.clone(),
+        value: c.value.clone(),
+    });
+    let additional_information = o.additional_information.iter().flat_map(|i| {
+        i.values.iter().map(|v| Attribute {
+            key: i.key.clone(),
+            label: i.key.clone(),
+            value: v
+                .split_once("::")
+                .map_or(v.as_str(), |(_, v)| v)
+                .to_string(),
+        })
+    });
+
+    characteristics.chain(additional_information).collect()
+}
+
+fn find_characteristic<'a>(o: &'a StoriaClassified, key: &str) -> Option<&'a str> {
+    o.characteristics
+        .iter()
+        .find(|c| c.key == key)
+        .map(|c| c.value.as_str())
+}
+
+/// Storia floors are `fl_2`, `ground_floor` and the like.
+fn parse_floor(value: &str) -> anyhow::Result<Option<i16>> {
+    match value {
+        "ground_floor" => Ok(Some(0)),
+        "cellar" => Ok(Some(-1)),
+        "higher_10" | "garret" => Ok(None),
+        v => v
+            .strip_prefix("fl_")
+            .ok_or_else(|| anyhow!("Unknown floor value \"{}\".", v))?
+            .parse()
+            .map(Some)
+            .context("Failed to parse floor."),
+    }
+}
+
+/// Values of an additional information entry, without the `commodity::` like prefix.
+fn additional_values<'a>(o: &'a StoriaClassified, key: &str) -> Vec<&'a str> {
+    o.additional_information
+        .iter()
+        .filter(|i| i.key == key)
+        .flat_map(|i| i.values.iter())
+        .map(|v| v.split_once("::").map_or(v.as_str(), |(_, v)| v))
+        .collect()
+}
+
+pub fn parse_classified(session: &uuid::Uuid, page: &SavedPage) -> anyhow::Result<Extraction> {
     let json = extract_page_json(page).context("Failed extracting Storia JSON from page.")?;
 
     let wrapper: StoriaClassifiedOuterWrapper =
         serde_json::from_str(json.as_str()).context("Failed parsing Storia JSON.")?;
 
     let o = wrapper.props.page_props.ad;
+    let mut d = Diagnostics::default();
+    let description = html_text(&o.description);
+    let commodities = additional_values(&o, "commodities");
+    let extras = additional_values(&o, "extras_types");
 
     let (raw_price, raw_currency): (&str, &str) = o
         .characteristics
@@ -114,76 +199,148 @@ pub fn parse_classified<'a, 'b>(
         })
         .ok_or_else(|| anyhow::anyhow!("Failed to find price characteristic"))?;
 
-    Ok(Classified {
-        session,
-        url: &page.url,
-        orientation: o
-            .characteristics
-            .iter()
-            .find_map(|i| {
-                if i.label == "main_solar_orient" {
-                    return Some(i.value.as_str().trim());
-                }
-                None
+    let classified = Classified {
+        session: *session,
+        url: page.url.clone(),
+        attributes: attributes(&o),
+        balcony_count: d
+            .warning(
+                "balcony_count",
+                find_characteristic(&o, "balconies_num")
+                    .map(|v| v.parse().context("Failed to parse balcony count."))
+                    .transpose(),
+            )
+            .or_else(|| extras.contains(&"balcony").then_some(1))
+            .or_else(|| find_balcony_count_in_str(&description)),
+        building_material: d
+            .warning(
+                "building_material",
+                find_characteristic(&o, "building_material")
+                    .map(BuildingMaterial::try_from)
+                    .transpose(),
+            )
+            .or_else(|| BuildingMaterial::find_in_str(&description)),
+        // "Brasov (judet), Brasov", county first.
+        city: o
+            .location
+            .address
+            .city
+            .as_ref()
+            .map(|c| c.name.rsplit(", ").next().unwrap_or(&c.name).to_string()),
+        elevator: d
+            .warning(
+                "elevator",
+                additional_values(&o, "elevator")
+                    .first()
+                    .map(|v| match *v {
+                        "y" => Ok(true),
+                        "n" => Ok(false),
+                        v => Err(anyhow!("Failed to parse elevator value \"{}\".", v)),
+                    })
+                    .transpose(),
+            )
+            .or_else(|| extras.contains(&"lift").then_some(true))
+            .or_else(|| find_elevator_in_str(&description)),
+        furnishing: commodities
+            .contains(&"furnished")
+            .then_some(Furnishing::Furnished)
+            .or_else(|| Furnishing::find_in_str(&description)),
+        heating: d
+            .warning(
+                "heating",
+                find_characteristic(&o, "heating")
+                    .map(HeatingType::try_from)
+                    .transpose(),
+            )
+            .or_else(|| {
+                commodities
+                    .contains(&"central_heating")
+                    .then_some(HeatingType::Central)
             })
-            .map(|o| CardinalDirection::try_from(o).map(Some))
-            .unwrap_or(Ok(None))?,
-        floor: o.top_information.iter().find_map(|p| {
-            if p.label == "floor" {
-                if let Some(f) = p.values.iter().find_map(|v| v.strip_prefix('/')) {
-                    return f.parse::<i16>().ok();
-                }
-            }
-            None
-        }),
-        layout: o.characteristics.iter().find_map(|c| {
-            if c.key == "divisioning_type" {
-                return Layout::try_from(c.localized_value.as_str()).ok();
-            }
-            None
-        }),
+            .or_else(|| HeatingType::find_in_str(&description)),
+        orientations: d
+            .error(
+                "orientations",
+                additional_values(&o, "main_solar_orient")
+                    .into_iter()
+                    .map(CardinalDirection::try_from)
+                    .collect::<anyhow::Result<_>>()
+                    .map(Some),
+            )
+            .unwrap_or_default(),
+        floor: d.error(
+            "floor",
+            find_characteristic(&o, "floor_no")
+                .map(parse_floor)
+                .transpose()
+                .map(Option::flatten),
+        ),
+        latitude: o.location.coordinates.as_ref().map(|c| c.latitude),
+        layout: d.warning(
+            "layout",
+            o.characteristics
+                .iter()
+                .find(|c| c.key == "divisioning_type")
+                .map(|c| Layout::try_from(c.localized_value.as_str()))
+                .transpose(),
+        ),
+        longitude: o.location.coordinates.as_ref().map(|c| c.longitude),
         negotiable: false,
+        parking: commodities
+            .iter()
+            .chain(extras.iter())
+            .find_map(|v| Parking::try_from(*v).ok())
+            .or_else(|| Parking::find_in_str(&description)),
         price: raw_price.parse().context("Failed to parse price.")?,
         currency: Currency::try_from(raw_currency)?,
-        property_type: o
-            .characteristics
-            .iter()
-            .find_map(|c| {
-                if c.key == "building_type" {
-                    return Some(c.localized_value.as_str());
-                }
-                None
-            })
-            .ok_or_else(|| anyhow!("Failed to find property type."))
-            .map(PropertyType::try_from)??,
+        property_type: d.error(
+            "property_type",
+            o.characteristics
+                .iter()
+                .find(|c| c.key == "building_type")
+                .ok_or_else(|| anyhow!("Failed to find property type."))
+                .and_then(|c| PropertyType::try_from(c.localized_value.as_str()))
+                .map(Some),
+        ),
         published_at: o.created_at,
-        room_count: o
-            .characteristics
-            .iter()
-            .find_map(|c| {
-                if c.key == "rooms_num" {
-                    return Some(
-                        c.value
-                            .parse::<i16>()
-                            .map(Some)
-                            .context("Failed to parse room count."),
-                    );
-                }
-                None
-            })
-            .unwrap_or(Ok(None))?,
+        room_count: d.error(
+            "room_count",
+            find_characteristic(&o, "rooms_num")
+                .map(|v| v.parse::<i16>().context("Failed to parse room count."))
+                .transpose(),
+        ),
+        // Usable area when there is one, like OLX has it.
+        surface: d.warning(
+            "surface",
+            find_characteristic(&o, "net_area")
+                .or_else(|| find_characteristic(&o, "m"))
+                .map(|v| {
+                    v.parse::<f64>()
+                        .map(|v| v.round() as i32)
+                        .context("Failed to parse surface.")
+                })
+                .transpose(),
+        ),
         seller_name: o.owner.name,
         seller_type: SellerType::Private,
-        surface: None,
+        description,
         title: o.title,
         year: None,
+    };
+
+    Ok(Extraction {
+        classified,
+        issues: d.issues,
     })
 }
 
 #[cfg(test)]
 mod tests {
     use super::{super::extractor::SavedPage, extract_page_json, parse_classified};
-    use crate::page::PageType;
+    use crate::{
+        extract::classified::{Furnishing, HeatingType},
+        page::PageType,
+    };
 
     const ITEMS: [(&str, &str); 1] = [(
         "https://www.storia.ro/ro/oferta/inchiriere-garsoniera-lux-urban-plaza-IDtVQ3.html",
@@ -220,4 +377,46 @@ mod tests {
             panic!("{:?}", e);
         }
     }
+
+    #[test]
+    fn keeps_raw_attributes() {
+        let (url, file) = ITEMS[0];
+        let session = uuid::Uuid::new_v4();
+        let page = SavedPage {
+            url: url.into(),
+            content: String::from_utf8(std::fs::read(file).unwrap()).unwrap(),
+            page_type: PageType::StoriaItem,
+            crawled_at: chrono::offset::Utc::now(),
+        };
+
+        let classified = parse_classified(&session, &page).unwrap().classified;
+
+        assert!(classified
+            .attributes
+            .iter()
+            .any(|a| a.key == "commodities" && a.value == "furnished"));
+        assert!(classified
+            .attributes
+            .iter()
+            .any(|a| a.key == "advertiser_type" && a.value == "agency"));
+        assert!(!classified.description.contains("<p>"));
+    }
+
+    #[test]
+    fn typed_amenities() {
+        let (url, file) = ITEMS[0];
+        let session = uuid::Uuid::new_v4();
+        let page = SavedPage {
+            url: url.into(),
+            content: String::from_utf8(std::fs::read(file).unwrap()).unwrap(),
+            page_type: PageType::StoriaItem,
+            crawled_at: chrono::offset::Utc::now(),
+        };
+
+        let classified = parse_classified(&session, &page).unwrap().classified;
+
+        assert!(matches!(classified.heating, Some(HeatingType::Central)));
+        assert!(matches!(classified.furnishing, Some(Furnishing::Furnished)));
+        assert_eq!(classified.elevator, Some(true));
+    }
 }
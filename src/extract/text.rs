@@ -0,0 +1,218 @@
+/// Words that flip the meaning of a match when found right before it
+/// ("fara lift", "nu este mobilat").
+const NEGATIONS: [&str; 5] = ["nu", "fara", "nici", "lipsa", "non"];
+
+/// How many words before a match are checked for a negation.
+const NEGATION_WINDOW: usize = 2;
+
+/// Lowercases and folds Romanian diacritics, both the comma and the cedilla
+/// variants, keeping everything else as is.
+pub fn fold(s: &str) -> String {
+    s.trim()
+        .chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| match c {
+            'ă' | 'â' | 'á' | 'à' => 'a',
+            'î' | 'í' => 'i',
+            'ș' | 'ş' => 's',
+            'ț' | 'ţ' => 't',
+            'é' => 'e',
+            'ó' => 'o',
+            c => c,
+        })
+        .collect()
+}
+
+/// Folds `s`, then everything that is not a letter or a digit becomes a
+/// single space, so words can be compared one by one.
+pub fn normalize(s: &str) -> String {
+    fold(s)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Keeps only the text of an HTML description, one line per text node, plain
+/// text stays as it is.
+pub fn html_text(html: &str) -> String {
+    scraper::Html::parse_fragment(html)
+        .root_element()
+        .text()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Maps any of the `phrases` to `value`. A phrase matches whole words only,
+/// a trailing `*` on its last word matches any suffix ("mobilat*" matches
+/// "mobilata"). A match preceded by a negation resolves to `negated`, or is
+/// ignored when the rule has no negated value.
+pub struct Rule<T> {
+    pub value: T,
+    pub negated: Option<T>,
+    pub phrases: &'static [&'static str],
+}
+
+/// Normalized text, split in words, ready to be matched against rules.
+pub struct Text {
+    words: Vec<String>,
+}
+
+impl Text {
+    pub fn new(s: &str) -> Self {
+        Self {
+            words: normalize(s).split(' ').map(String::from).collect(),
+        }
+    }
+
+    pub fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    /// Whether the word at `index` is preceded by a negation.
+    pub fn is_negated(&self, index: usize) -> bool {
+        self.words[index.saturating_sub(NEGATION_WINDOW)..index]
+            .iter()
+            .any(|w| NEGATIONS.contains(&w.as_str()))
+    }
+
+    /// Start indexes of every occurrence of `phrase`.
+    pub fn positions(&self, phrase: &str) -> Vec<usize> {
+        let pattern = phrase.split(' ').collect::<Vec<_>>();
+        if pattern.is_empty() || pattern.len() > self.words.len() {
+            return vec![];
+        }
+
+        (0..=self.words.len() - pattern.len())
+            .filter(|&start| {
+                pattern.iter().enumerate().all(|(i, p)| {
+                    let word = self.words[start + i].as_str();
+                    match p.strip_suffix('*') {
+                        Some(prefix) => word.starts_with(prefix),
+                        None => word == *p,
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// Value of every rule with a usable match, in rule order. Words matched by
+    /// a rule can't be matched again by a later one.
+    pub fn find_all<T: Copy + PartialEq>(&self, rules: &[Rule<T>]) -> Vec<T> {
+        let mut used = vec![false; self.words.len()];
+        let mut found = vec![];
+        for rule in rules {
+            for phrase in rule.phrases {
+                let len = phrase.split(' ').count();
+                for position in self.positions(phrase) {
+                    if used[position..position + len].iter().any(|&u| u) {
+                        continue;
+                    }
+                    used[position..position + len].fill(true);
+                    let value = match self.is_negated(position) {
+                        true => rule.negated,
+                        false => Some(rule.value),
+                    };
+                    if let Some(value) = value.filter(|v| !found.contains(v)) {
+                        found.push(value);
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    /// Value of the first rule, in order, that has a usable match.
+    pub fn find<T: Copy>(&self, rules: &[Rule<T>]) -> Option<T> {
+        rules.iter().find_map(|rule| {
+            rule.phrases
+                .iter()
+                .flat_map(|phrase| self.positions(phrase))
+                .find_map(|position| match self.is_negated(position) {
+                    true => rule.negated,
+                    false => Some(rule.value),
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fold, html_text, normalize, Rule, Text};
+
+    const RULES: [Rule<&str>; 3] = [
+        Rule {
+            value: "semi",
+            negated: None,
+            phrases: &["semidecomandat", "semi decomandat"],
+        },
+        Rule {
+            value: "fancy",
+            negated: None,
+            phrases: &["decomandat*"],
+        },
+        Rule {
+            value: "lift",
+            negated: Some("no lift"),
+            phrases: &["lift", "ascensor"],
+        },
+    ];
+
+    #[test]
+    fn html_descriptions_become_plain_text() {
+        assert_eq!(
+            html_text("<p>Apartament 2 camere<br/></p>\r\n<p>- <b>mobilat</b></p>"),
+            "Apartament 2 camere\n-\nmobilat"
+        );
+        assert_eq!(
+            html_text("Etaj parter, mobilata."),
+            "Etaj parter, mobilata."
+        );
+    }
+
+    #[test]
+    fn folds_diacritics_and_case() {
+        assert_eq!(normalize("Sudică, ESTICĂ"), "sudica estica");
+        assert_eq!(normalize("Încălzire ș/ş ț/ţ"), "incalzire s s t t");
+        assert_eq!(normalize("  semi-decomandat!! "), "semi decomandat");
+    }
+
+    #[test]
+    fn fold_keeps_punctuation() {
+        assert_eq!(fold(" Semi_Mobilat "), "semi_mobilat");
+        assert_eq!(fold("Sudică"), "sudica");
+    }
+
+    #[test]
+    fn matches_whole_words_only() {
+        assert_eq!(Text::new("apartament nedecomandat").find(&RULES), None);
+        assert_eq!(Text::new("Decomandată").find(&RULES), Some("fancy"));
+        assert_eq!(Text::new("semi-decomandat").find(&RULES), Some("semi"));
+    }
+
+    #[test]
+    fn rule_order_wins() {
+        assert_eq!(
+            Text::new("decomandat sau semidecomandat").find(&RULES),
+            Some("semi")
+        );
+    }
+
+    #[test]
+    fn find_all_does_not_reuse_words() {
+        assert_eq!(
+            Text::new("semi-decomandat, bloc cu lift").find_all(&RULES),
+            vec!["semi", "lift"]
+        );
+    }
+
+    #[test]
+    fn handles_negations() {
+        assert_eq!(Text::new("bloc fără lift").find(&RULES), Some("no lift"));
+        assert_eq!(Text::new("nu are ascensor").find(&RULES), Some("no lift"));
+        assert_eq!(Text::new("bloc cu lift").find(&RULES), Some("lift"));
+        assert_eq!(Text::new("nu e decomandat").find(&RULES), None);
+    }
+}
@@ -0,0 +1,86 @@
+use anyhow::Context;
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::{
+    crawler::page::{get_page, save_test_asset, validate_page},
+    extract::{
+        diagnostics::Extraction,
+        extractor::{parse_page, SavedPage},
+        olx, storia,
+    },
+    page::{PageType, PageUrl},
+};
+
+/// Fetches a single classified the way the crawler would and prints what the
+/// extractor makes of it, nothing is saved in the database.
+#[derive(clap::Args)]
+pub struct InspectCmd {
+    pub url: String,
+    /// Also save the fetched page under this name in the test assets.
+    #[arg(long)]
+    pub save_asset: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct Inspection<'a> {
+    url: &'a str,
+    page_type: PageType,
+    extraction: Option<Extraction>,
+    error: Option<String>,
+    /// The JSON embedded in the page, as the source published it.
+    raw: Option<serde_json::Value>,
+}
+
+fn raw_json(page: &SavedPage) -> anyhow::Result<serde_json::Value> {
+    let json = match page.page_type {
+        PageType::OlxList => return Err(anyhow::anyhow!("List pages have no embedded JSON.")),
+        PageType::OlxItem => olx::extract_page_json(page)?,
+        PageType::StoriaItem => storia::extract_page_json(page)?,
+    };
+    serde_json::from_str(&json).context("Failed to parse the embedded JSON.")
+}
+
+impl InspectCmd {
+    pub fn work(&self) -> anyhow::Result<()> {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("Failed building the Runtime")
+            .block_on(async move {
+                let page_url = PageUrl::parse(&self.url)?;
+                let page = SavedPage {
+                    content: get_page(page_url.as_ref()).await?,
+                    crawled_at: Utc::now(),
+                    page_type: PageType::from(&page_url),
+                    url: String::from(page_url),
+                };
+
+                // Saved before validating, blocked pages make good assets too.
+                if let Some(name) = &self.save_asset {
+                    save_test_asset(name, &page.content)?;
+                }
+
+                validate_page(page.page_type, &page.content)?;
+
+                let session = Uuid::nil();
+                let (extraction, error) = match parse_page(&session, &page) {
+                    Ok(extraction) => (Some(extraction), None),
+                    Err(e) => (None, Some(format!("{:#}", e))),
+                };
+                let inspection = Inspection {
+                    url: &page.url,
+                    page_type: page.page_type,
+                    extraction,
+                    error,
+                    raw: raw_json(&page).ok(),
+                };
+
+                serde_json::to_writer_pretty(std::io::stdout(), &inspection)
+                    .context("Failed to serialize the inspection.")?;
+                println!();
+
+                Ok(())
+            })
+    }
+}
@@ -1,6 +1,10 @@
+pub mod ads;
 pub mod config;
 pub mod crawler;
+pub mod daemon;
+pub mod dedup;
 pub mod extract;
+pub mod inspect;
 pub mod util;
 pub mod page;
 pub mod session;
@@ -1,10 +1,14 @@
 use anyhow::Context;
 use clap::Parser;
 use olx_scrapie::{
+    ads::HistoryCmd,
     config::Config,
     crawler::command::CrawlCmd,
-    extract::command::ExtractCmd,
-    session::ListSessionsCmd,
+    daemon::DaemonCmd,
+    dedup::DedupCmd,
+    extract::command::{ExtractCmd, ExtractFileCmd},
+    inspect::InspectCmd,
+    session::{command::SessionCmd, list::ListSessionsCmd},
 };
 
 /// Search for a pattern in a file and display the lines that contain it.
@@ -17,21 +21,34 @@ struct Cli {
 #[derive(clap::Subcommand)]
 enum Commands {
     ListSessions(ListSessionsCmd),
+    Session(SessionCmd),
     Crawl(CrawlCmd),
     Extract(ExtractCmd),
+    ExtractFile(ExtractFileCmd),
+    Inspect(InspectCmd),
+    History(HistoryCmd),
+    Dedup(DedupCmd),
+    Daemon(DaemonCmd),
 }
 
 fn main() -> anyhow::Result<()> {
     dotenvy::dotenv().ok();
-    let cfg = Config::from_env().context("Failed to load the env configuration.")?;
-
     let args = Cli::parse();
 
     // let update_assets = args.update_assets.unwrap_or(false);
 
+    // Loaded only by the commands that need it, the offline ones work without a .env.
+    let cfg = || Config::from_env().context("Failed to load the env configuration.");
+
     match args.command {
-        Commands::ListSessions(cmd) => cmd.work(&cfg),
-        Commands::Crawl(cmd) => cmd.work(&cfg),
-        Commands::Extract(cmd) => cmd.work(&cfg),
+        Commands::ListSessions(cmd) => cmd.work(&cfg()?),
+        Commands::Session(cmd) => cmd.work(&cfg()?),
+        Commands::Crawl(cmd) => cmd.work(&cfg()?),
+        Commands::Extract(cmd) => cmd.work(&cfg()?),
+        Commands::ExtractFile(cmd) => cmd.work(),
+        Commands::Inspect(cmd) => cmd.work(),
+        Commands::History(cmd) => cmd.work(&cfg()?),
+        Commands::Dedup(cmd) => cmd.work(&cfg()?),
+        Commands::Daemon(cmd) => cmd.work(&cfg()?),
     }
 }
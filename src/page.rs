@@ -2,6 +2,9 @@ use chrono::Utc;
 use url::Url;
 use uuid::Uuid;
 
+/// Postgres channel notified with the session UUID every time a page is saved.
+pub const PAGES_SAVED_CHANNEL: &str = "pages_saved";
+
 #[derive(sqlx::FromRow)]
 pub struct SavedPage<'a> {
     pub content: String,
@@ -11,8 +14,10 @@ pub struct SavedPage<'a> {
     pub url: String,
 }
 
-#[derive(sqlx::Type, Copy, Clone)]
+#[derive(sqlx::Type, clap::ValueEnum, serde::Serialize, Copy, Clone)]
 #[sqlx(type_name = "page_type", rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
 pub enum PageType {
     OlxList,
     OlxItem,
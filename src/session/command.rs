@@ -0,0 +1,26 @@
+use crate::config::Config;
+
+use super::{delete::SessionDeleteCmd, prune::SessionPruneCmd, stats::SessionStatsCmd};
+
+#[derive(clap::Args)]
+pub struct SessionCmd {
+    #[command(subcommand)]
+    command: SessionCommands,
+}
+
+#[derive(clap::Subcommand)]
+enum SessionCommands {
+    Stats(SessionStatsCmd),
+    Delete(SessionDeleteCmd),
+    Prune(SessionPruneCmd),
+}
+
+impl SessionCmd {
+    pub fn work(&self, config: &Config) -> anyhow::Result<()> {
+        match &self.command {
+            SessionCommands::Stats(cmd) => cmd.work(config),
+            SessionCommands::Delete(cmd) => cmd.work(config),
+            SessionCommands::Prune(cmd) => cmd.work(config),
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use anyhow::Context;
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    util::{try_parse_session, PgTransaction},
+};
+
+/// Deletes everything stored for a session.
+#[derive(clap::Args)]
+pub struct SessionDeleteCmd {
+    pub session: String,
+    /// Show what would be deleted, without deleting it.
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+impl SessionDeleteCmd {
+    pub fn work(&self, config: &Config) -> anyhow::Result<()> {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("Failed building the Runtime")
+            .block_on(async move {
+                let session = try_parse_session(&self.session)?;
+                let pool = sqlx::postgres::PgPoolOptions::new()
+                    .connect(config.database_url.as_ref())
+                    .await
+                    .context("Failed to establish connection to postgres.")?;
+
+                // A dry run deletes just the same, then rolls back, so the
+                // counts are exact.
+                let mut transaction = pool.begin().await?;
+                let deleted = delete_session(&mut transaction, &session)
+                    .await
+                    .context("Failed to delete the session.")?;
+                if deleted.is_empty() {
+                    return Err(anyhow::anyhow!("No session found in DB."));
+                }
+
+                for (table, count) in &deleted {
+                    println!("{:<17} | {}", table, count);
+                }
+
+                match self.dry_run {
+                    true => {
+                        transaction.rollback().await?;
+                        println!("Dry run, nothing was deleted.");
+                    }
+                    false => transaction.commit().await?,
+                };
+
+                Ok(())
+            })
+    }
+}
+
+/// Deletes the session rows, dependants first. Ads only seen by this session
+/// are deleted, the others get their first/last seen session moved to the
+/// closest other session that discovered them. Returns the affected rows per
+/// table, empty when there is no such session.
+pub async fn delete_session<'a>(
+    transaction: &mut PgTransaction<'a>,
+    session: &Uuid,
+) -> sqlx::Result<Vec<(&'static str, u64)>> {
+    let exists = sqlx::query!(
+        "SELECT session FROM sessions WHERE session=$1 FOR UPDATE",
+        session
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    if exists.is_none() {
+        return Ok(vec![]);
+    }
+
+    let extraction_issues = sqlx::query!("DELETE FROM extraction_issues WHERE session=$1", session)
+        .execute(&mut *transaction)
+        .await?;
+    let classifieds = sqlx::query!("DELETE FROM classifieds WHERE session=$1", session)
+        .execute(&mut *transaction)
+        .await?;
+    let pages = sqlx::query!("DELETE FROM pages WHERE session=$1", session)
+        .execute(&mut *transaction)
+        .await?;
+
+    let ads = sqlx::query!(
+        r#"
+        DELETE FROM ads
+        WHERE
+          (first_seen_session=$1 OR last_seen_session=$1)
+          AND NOT EXISTS (
+            SELECT 1
+            FROM crawler_queue q
+            WHERE q.session<>$1 AND ad_id_from_url(q.url)=ads.ad_id
+          )
+        "#,
+        session,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let ads_moved = sqlx::query!(
+        r#"
+        UPDATE ads
+        SET
+          (first_seen_session, first_seen_at) = (
+            SELECT s.session, s.created_at
+            FROM crawler_queue q
+            JOIN sessions s USING (session)
+            WHERE q.session<>$1 AND ad_id_from_url(q.url)=ads.ad_id
+            ORDER BY s.created_at
+            LIMIT 1
+          ),
+          (last_seen_session, last_seen_at) = (
+            SELECT s.session, s.created_at
+            FROM crawler_queue q
+            JOIN sessions s USING (session)
+            WHERE q.session<>$1 AND ad_id_from_url(q.url)=ads.ad_id
+            ORDER BY s.created_at DESC
+            LIMIT 1
+          )
+        WHERE first_seen_session=$1 OR last_seen_session=$1
+        "#,
+        session,
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    let crawler_queue = sqlx::query!("DELETE FROM crawler_queue WHERE session=$1", session)
+        .execute(&mut *transaction)
+        .await?;
+    let crawler_workers = sqlx::query!("DELETE FROM crawler_workers WHERE session=$1", session)
+        .execute(&mut *transaction)
+        .await?;
+    let crawl_budgets = sqlx::query!("DELETE FROM crawl_budgets WHERE session=$1", session)
+        .execute(&mut *transaction)
+        .await?;
+    let sessions = sqlx::query!("DELETE FROM sessions WHERE session=$1", session)
+        .execute(&mut *transaction)
+        .await?;
+
+    Ok(vec![
+        ("extraction_issues", extraction_issues.rows_affected()),
+        ("classifieds", classifieds.rows_affected()),
+        ("pages", pages.rows_affected()),
+        ("ads", ads.rows_affected()),
+        ("ads (moved)", ads_moved.rows_affected()),
+        ("crawler_queue", crawler_queue.rows_affected()),
+        ("crawler_workers", crawler_workers.rows_affected()),
+        ("crawl_budgets", crawl_budgets.rows_affected()),
+        ("sessions", sessions.rows_affected()),
+    ])
+}
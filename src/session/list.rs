@@ -0,0 +1,217 @@
+use std::io::Write;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    util::{csv_row, parse_since, OutputFormat},
+};
+
+use super::SessionStatus;
+
+#[derive(clap::ValueEnum, Copy, Clone)]
+pub enum SortBy {
+    Created,
+    Crawled,
+    Pages,
+    Classifieds,
+}
+
+#[derive(clap::Args)]
+pub struct ListSessionsCmd {
+    /// Sessions created since, either a duration ago (7d) or a date (2023-01-31).
+    #[arg(long, value_parser = parse_since)]
+    pub since: Option<DateTime<Utc>>,
+    /// Only sessions whose search URL contains this.
+    #[arg(long)]
+    pub search: Option<String>,
+    #[arg(long, value_enum)]
+    pub status: Option<SessionStatus>,
+    /// Sorted newest or biggest first, unless --asc.
+    #[arg(long, value_enum, default_value_t = SortBy::Created)]
+    pub sort: SortBy,
+    #[arg(long)]
+    pub asc: bool,
+    #[arg(long)]
+    pub limit: Option<usize>,
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+}
+
+#[derive(serde::Serialize)]
+pub struct SessionSummary {
+    pub session: Uuid,
+    pub status: SessionStatus,
+    /// Why the session failed or was aborted.
+    pub status_reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub crawled_at: Option<DateTime<Utc>>,
+    pub search: Option<String>,
+    pub pages: i64,
+    pub classifieds: i64,
+}
+
+impl SortBy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Created => "created",
+            Self::Crawled => "crawled",
+            Self::Pages => "pages",
+            Self::Classifieds => "classifieds",
+        }
+    }
+}
+
+impl ListSessionsCmd {
+    pub fn work(&self, config: &Config) -> anyhow::Result<()> {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("Failed building the Runtime")
+            .block_on(async move {
+                tracing_appender::rolling::never("logs", "session.log");
+                let pool = sqlx::postgres::PgPoolOptions::new()
+                    .connect(config.database_url.as_ref())
+                    .await
+                    .context("Failed to establish connection to postgres.")?;
+
+                let sessions = self
+                    .list(&pool)
+                    .await
+                    .context("Failed to load sessions from database.")?;
+                write_sessions(std::io::stdout(), &sessions, self.format)
+            })
+    }
+
+    /// The matching sessions, sorted and limited.
+    pub async fn list(&self, pool: &PgPool) -> sqlx::Result<Vec<SessionSummary>> {
+        let sessions = sqlx::query!(
+            r#"
+            SELECT
+              session AS "session!",
+              created_at AS "created_at!",
+              crawled_at,
+              search,
+              status AS "status!: SessionStatus",
+              status_reason,
+              pages AS "pages!",
+              classifieds AS "classifieds!"
+            FROM (
+              SELECT
+                s.*,
+                (SELECT COUNT(*) FROM pages p WHERE p.session=s.session) AS pages,
+                (
+                  SELECT COUNT(*) FROM latest_classifieds c WHERE c.session=s.session
+                ) AS classifieds
+              FROM sessions s
+              WHERE
+                ($1::TIMESTAMPTZ IS NULL OR s.created_at >= $1)
+                AND ($2::TEXT IS NULL OR s.search ILIKE '%' || $2 || '%')
+                AND ($3::session_status IS NULL OR s.status=$3)
+            ) s
+            ORDER BY
+              CASE WHEN $4='created' AND $5 THEN created_at END ASC,
+              CASE WHEN $4='created' AND NOT $5 THEN created_at END DESC,
+              CASE WHEN $4='crawled' AND $5 THEN crawled_at END ASC NULLS FIRST,
+              CASE WHEN $4='crawled' AND NOT $5 THEN crawled_at END DESC NULLS LAST,
+              CASE WHEN $4='pages' AND $5 THEN pages END ASC,
+              CASE WHEN $4='pages' AND NOT $5 THEN pages END DESC,
+              CASE WHEN $4='classifieds' AND $5 THEN classifieds END ASC,
+              CASE WHEN $4='classifieds' AND NOT $5 THEN classifieds END DESC,
+              session
+            LIMIT $6
+            "#,
+            self.since,
+            self.search,
+            self.status as Option<SessionStatus>,
+            self.sort.as_str(),
+            self.asc,
+            self.limit.map(|limit| limit as i64),
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|s| SessionSummary {
+            session: s.session,
+            status: s.status,
+            status_reason: s.status_reason,
+            created_at: s.created_at,
+            crawled_at: s.crawled_at,
+            search: s.search,
+            pages: s.pages,
+            classifieds: s.classifieds,
+        })
+        .collect();
+        Ok(sessions)
+    }
+}
+
+pub fn write_sessions<W: Write>(
+    mut out: W,
+    sessions: &[SessionSummary],
+    format: OutputFormat,
+) -> anyhow::Result<()> {
+    match format {
+        OutputFormat::Table => {
+            for s in sessions {
+                writeln!(
+                    out,
+                    "{} | {:<10} | {} | {} | {:>6} | {:>6} | {}{}",
+                    s.session,
+                    s.status.to_string(),
+                    s.created_at,
+                    s.crawled_at
+                        .map_or("-".into(), |crawled_at| crawled_at.to_string()),
+                    s.pages,
+                    s.classifieds,
+                    s.search.as_deref().unwrap_or("-"),
+                    s.status_reason
+                        .as_ref()
+                        .map_or(String::new(), |reason| format!(" | {}", reason)),
+                )?;
+            }
+        }
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, sessions)
+                .context("Failed to serialize the sessions.")?;
+            writeln!(out)?;
+        }
+        OutputFormat::Csv => {
+            writeln!(
+                out,
+                "{}",
+                csv_row(&[
+                    "session",
+                    "status",
+                    "status_reason",
+                    "created_at",
+                    "crawled_at",
+                    "search",
+                    "pages",
+                    "classifieds"
+                ])
+            )?;
+            for s in sessions {
+                writeln!(
+                    out,
+                    "{}",
+                    csv_row(&[
+                        s.session.to_string(),
+                        s.status.to_string(),
+                        s.status_reason.clone().unwrap_or_default(),
+                        s.created_at.to_rfc3339(),
+                        s.crawled_at.map_or(String::new(), |c| c.to_rfc3339()),
+                        s.search.clone().unwrap_or_default(),
+                        s.pages.to_string(),
+                        s.classifieds.to_string(),
+                    ])
+                )?;
+            }
+        }
+    };
+
+    Ok(())
+}
@@ -0,0 +1,179 @@
+pub mod command;
+pub mod delete;
+pub mod list;
+pub mod prune;
+pub mod stats;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::util::PgTransaction;
+
+#[derive(sqlx::Type, clap::ValueEnum, serde::Serialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[sqlx(type_name = "session_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum SessionStatus {
+    Created,
+    Crawling,
+    Crawled,
+    /// Crawl stopped on a budget limit, with jobs left.
+    PartiallyCrawled,
+    Extracting,
+    Extracted,
+    Failed,
+    Aborted,
+}
+
+impl SessionStatus {
+    /// Whether a session can go from `self` to `to`, `crawled` tells if the
+    /// crawl ever completed. Crawls and extractions can be resumed after they
+    /// failed, were aborted or died without a trace (crawling to crawling), a
+    /// crawled session is reopened when its jobs get requeued.
+    pub fn can_transition(self, to: Self, crawled: bool) -> bool {
+        use SessionStatus::*;
+
+        match to {
+            Created => false,
+            Crawling => self != Extracting,
+            Crawled | PartiallyCrawled => self == Crawling,
+            Extracting => {
+                crawled
+                    && matches!(
+                        self,
+                        Crawled | PartiallyCrawled | Extracting | Extracted | Failed | Aborted
+                    )
+            }
+            // Straight from crawled when extracting while crawling.
+            Extracted => matches!(self, Crawled | PartiallyCrawled | Extracting),
+            Failed | Aborted => matches!(self, Created | Crawling | Extracting),
+        }
+    }
+}
+
+impl std::fmt::Display for SessionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Created => "created",
+                Self::Crawling => "crawling",
+                Self::Crawled => "crawled",
+                Self::PartiallyCrawled => "partially_crawled",
+                Self::Extracting => "extracting",
+                Self::Extracted => "extracted",
+                Self::Failed => "failed",
+                Self::Aborted => "aborted",
+            }
+        )
+    }
+}
+
+/// Moves the session to `to`, recording when it happened and, for partially
+/// crawled, failed or aborted sessions, why. Fails if the transition is not allowed.
+#[tracing::instrument(skip(transaction))]
+pub async fn transition<'a>(
+    transaction: &mut PgTransaction<'a>,
+    session: &Uuid,
+    to: SessionStatus,
+    reason: Option<&str>,
+) -> anyhow::Result<()> {
+    let current = sqlx::query!(
+        r#"
+        SELECT
+          status AS "status: SessionStatus",
+          crawled_at
+        FROM sessions
+        WHERE session=$1
+        FOR UPDATE
+        "#,
+        session,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed retrieving the session status.")?
+    .ok_or_else(|| anyhow::anyhow!("No session found in DB."))?;
+
+    if !current
+        .status
+        .can_transition(to, current.crawled_at.is_some())
+    {
+        return Err(anyhow::anyhow!(
+            "Session is {}, it cannot be {}.",
+            current.status,
+            to
+        ));
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE sessions
+        SET
+          status=$2::session_status,
+          status_reason=$3,
+          crawling_at=CASE WHEN $2='crawling'::session_status THEN CURRENT_TIMESTAMP ELSE crawling_at END,
+          crawled_at=CASE
+            WHEN $2 IN ('crawled'::session_status, 'partially_crawled'::session_status)
+              THEN CURRENT_TIMESTAMP
+            -- Reopened, it's not crawled until it completes again.
+            WHEN $2='crawling'::session_status THEN NULL
+            ELSE crawled_at
+          END,
+          extracting_at=CASE WHEN $2='extracting'::session_status THEN CURRENT_TIMESTAMP ELSE extracting_at END,
+          extracted_at=CASE WHEN $2='extracted'::session_status THEN CURRENT_TIMESTAMP ELSE extracted_at END,
+          failed_at=CASE WHEN $2='failed'::session_status THEN CURRENT_TIMESTAMP ELSE failed_at END,
+          aborted_at=CASE WHEN $2='aborted'::session_status THEN CURRENT_TIMESTAMP ELSE aborted_at END
+        WHERE session=$1
+        "#,
+        session,
+        to as SessionStatus,
+        reason,
+    )
+    .execute(transaction)
+    .await
+    .context("Failed updating the session status.")?;
+
+    tracing::info!("Session is {}.", to);
+    Ok(())
+}
+
+pub struct Session {
+    pub crawled_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub session: Uuid,
+    pub status: SessionStatus,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SessionStatus;
+
+    #[test]
+    fn enforces_transitions() {
+        use SessionStatus::*;
+
+        assert!(Created.can_transition(Crawling, false));
+        assert!(Crawling.can_transition(Crawling, false));
+        assert!(Crawling.can_transition(Crawled, false));
+        assert!(Crawled.can_transition(Extracting, true));
+        assert!(Crawled.can_transition(Extracted, true));
+        assert!(Extracting.can_transition(Extracted, true));
+        assert!(Extracted.can_transition(Extracting, true));
+        assert!(Crawling.can_transition(Failed, false));
+        assert!(Failed.can_transition(Crawling, false));
+        assert!(Aborted.can_transition(Extracting, true));
+        assert!(Crawled.can_transition(Crawling, true));
+        assert!(Extracted.can_transition(Crawling, true));
+        assert!(Crawling.can_transition(PartiallyCrawled, false));
+        assert!(PartiallyCrawled.can_transition(Extracting, true));
+        assert!(PartiallyCrawled.can_transition(Crawling, true));
+
+        assert!(!Extracting.can_transition(Crawling, true));
+        assert!(!Created.can_transition(Extracting, false));
+        assert!(!Failed.can_transition(Extracting, false));
+        assert!(!Extracted.can_transition(Failed, true));
+        assert!(!Crawling.can_transition(Created, false));
+        assert!(!Extracting.can_transition(PartiallyCrawled, true));
+    }
+}
@@ -0,0 +1,165 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    util::{parse_duration, PgTransaction},
+};
+
+/// Drops the raw HTML of old sessions, the extracted classifieds are kept.
+/// Only pages already extracted are pruned, along with the list pages.
+#[derive(clap::Args)]
+#[command(group(
+    clap::ArgGroup::new("policy")
+        .required(true)
+        .multiple(true)
+        .args(["keep_last", "older_than"])
+))]
+pub struct SessionPruneCmd {
+    /// Keep the pages of the N most recent sessions.
+    #[arg(long, value_parser = clap::value_parser!(i64).range(0..))]
+    pub keep_last: Option<i64>,
+    /// Prune only sessions created before this long ago (e.g. 90d, 12h, 2w).
+    #[arg(long, value_parser = parse_duration)]
+    pub older_than: Option<chrono::Duration>,
+    /// Show what would be pruned, without pruning it.
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+impl SessionPruneCmd {
+    pub fn work(&self, config: &Config) -> anyhow::Result<()> {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("Failed building the Runtime")
+            .block_on(async move {
+                let pool = sqlx::postgres::PgPoolOptions::new()
+                    .connect(config.database_url.as_ref())
+                    .await
+                    .context("Failed to establish connection to postgres.")?;
+
+                let policy = PrunePolicy {
+                    keep_last: self.keep_last,
+                    created_before: self.older_than.map(|d| Utc::now() - d),
+                    search: None,
+                };
+
+                let pruned = prune(&pool, &policy, self.dry_run).await?;
+
+                pruned.iter().for_each(|p| {
+                    println!("{} | {} pages | {} bytes", p.session, p.pages, p.bytes);
+                });
+                let bytes = pruned.iter().map(|p| p.bytes).sum::<i64>();
+                println!(
+                    "{} sessions, {:.1} MiB",
+                    pruned.len(),
+                    bytes as f64 / 1024.0 / 1024.0
+                );
+
+                if self.dry_run {
+                    println!("Dry run, nothing was pruned.");
+                }
+
+                Ok(())
+            })
+    }
+}
+
+/// Which sessions get their pages pruned.
+pub struct PrunePolicy<'a> {
+    /// Keep the pages of the N most recent sessions.
+    pub keep_last: Option<i64>,
+    pub created_before: Option<DateTime<Utc>>,
+    /// Only sessions of this search, the N most recent are counted among them.
+    pub search: Option<&'a str>,
+}
+
+pub struct PrunedSession {
+    pub session: Uuid,
+    pub pages: i64,
+    pub bytes: i64,
+}
+
+/// Prunes the pages matching the policy, or only tells which would be on a dry
+/// run.
+pub async fn prune(
+    pool: &PgPool,
+    policy: &PrunePolicy<'_>,
+    dry_run: bool,
+) -> anyhow::Result<Vec<PrunedSession>> {
+    let mut transaction = pool.begin().await?;
+    let pruned = prune_pages(&mut transaction, policy)
+        .await
+        .context("Failed to prune pages.")?;
+    match dry_run {
+        true => transaction.rollback().await?,
+        false => transaction.commit().await?,
+    };
+    Ok(pruned)
+}
+
+pub async fn prune_pages<'a>(
+    transaction: &mut PgTransaction<'a>,
+    policy: &PrunePolicy<'_>,
+) -> sqlx::Result<Vec<PrunedSession>> {
+    sqlx::query_as!(
+        PrunedSession,
+        r#"
+        WITH prunable AS (
+          SELECT
+            p.session,
+            p.url,
+            octet_length(p.content) AS bytes
+          FROM pages p
+          JOIN sessions s USING (session)
+          WHERE
+            p.content IS NOT NULL
+            AND s.crawled_at IS NOT NULL
+            AND ($2::TIMESTAMPTZ IS NULL OR s.created_at < $2)
+            AND ($3::TEXT IS NULL OR s.search=$3)
+            AND s.session NOT IN (
+              SELECT session
+              FROM sessions
+              WHERE $3::TEXT IS NULL OR search=$3
+              ORDER BY created_at DESC
+              LIMIT $1
+            )
+            AND (
+              p.page_type='olx_list'
+              OR EXISTS (
+                SELECT 1 FROM classifieds c WHERE c.session=p.session AND c.url=p.url
+              )
+              OR EXISTS (
+                SELECT 1
+                FROM extraction_issues i
+                WHERE i.session=p.session AND i.url=p.url AND i.severity='fatal'
+              )
+            )
+        ),
+        pruned AS (
+          UPDATE pages p
+          SET
+            content=NULL,
+            pruned_at=CURRENT_TIMESTAMP
+          FROM prunable
+          WHERE p.session=prunable.session AND p.url=prunable.url
+          RETURNING p.session, prunable.bytes
+        )
+        SELECT
+          session AS "session!",
+          COUNT(*) AS "pages!",
+          COALESCE(SUM(bytes), 0)::BIGINT AS "bytes!"
+        FROM pruned
+        GROUP BY session
+        ORDER BY session
+        "#,
+        policy.keep_last.unwrap_or(0),
+        policy.created_before,
+        policy.search,
+    )
+    .fetch_all(transaction)
+    .await
+}
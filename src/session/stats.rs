@@ -0,0 +1,323 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    page::PageType,
+    util::{csv_row, try_parse_session, OutputFormat},
+};
+
+use super::SessionStatus;
+
+#[derive(clap::Args)]
+pub struct SessionStatsCmd {
+    pub session: String,
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+    /// How many of the most frequent crawl errors to show.
+    #[arg(long, default_value_t = 5)]
+    pub top_errors: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct QueueCount {
+    pub page_type: PageType,
+    pub status: String,
+    pub count: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct MessageCount {
+    pub message: String,
+    pub count: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct SessionStats {
+    pub session: Uuid,
+    pub status: SessionStatus,
+    /// Why the session failed or was aborted.
+    pub status_reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub crawled_at: Option<DateTime<Utc>>,
+    /// Until now when the session is still being crawled.
+    pub crawl_duration_seconds: i64,
+    pub pages_per_minute: f64,
+    pub queue: Vec<QueueCount>,
+    pub pages: i64,
+    pub page_bytes: i64,
+    pub classifieds: i64,
+    /// Pages nothing could be extracted from.
+    pub extraction_failures: i64,
+    pub extraction_issues: Vec<MessageCount>,
+    pub ads: i64,
+    pub properties: i64,
+    /// Final failures and retried errors of the crawl.
+    pub top_errors: Vec<MessageCount>,
+}
+
+impl SessionStatsCmd {
+    pub fn work(&self, config: &Config) -> anyhow::Result<()> {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("Failed building the Runtime")
+            .block_on(async move {
+                let session = try_parse_session(&self.session)?;
+                let pool = sqlx::postgres::PgPoolOptions::new()
+                    .connect(config.database_url.as_ref())
+                    .await
+                    .context("Failed to establish connection to postgres.")?;
+
+                let stats = load_stats(&pool, &session, self.top_errors)
+                    .await
+                    .context("Failed to load the session stats.")?;
+
+                match self.format {
+                    OutputFormat::Table => print_table(&stats),
+                    OutputFormat::Json => {
+                        serde_json::to_writer_pretty(std::io::stdout(), &stats)
+                            .context("Failed to serialize the session stats.")?;
+                        println!();
+                    }
+                    OutputFormat::Csv => print_csv(&stats),
+                };
+
+                Ok(())
+            })
+    }
+}
+
+pub async fn load_stats(
+    pool: &PgPool,
+    session: &Uuid,
+    top_errors: i64,
+) -> anyhow::Result<SessionStats> {
+    let s = sqlx::query!(
+        r#"
+        SELECT
+          created_at,
+          crawled_at,
+          status AS "status: SessionStatus",
+          status_reason
+        FROM sessions
+        WHERE session=$1
+        "#,
+        session,
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| anyhow::anyhow!("No session found in DB."))?;
+
+    let queue = sqlx::query_as!(
+        QueueCount,
+        r#"
+        SELECT
+          page_type AS "page_type: _",
+          status::TEXT AS "status!",
+          COUNT(*) AS "count!"
+        FROM crawler_queue
+        WHERE session=$1
+        GROUP BY page_type, status
+        ORDER BY page_type, status
+        "#,
+        session,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let pages = sqlx::query!(
+        r#"
+        SELECT
+          COUNT(*) AS "count!",
+          COALESCE(SUM(octet_length(content)), 0)::BIGINT AS "bytes!"
+        FROM pages
+        WHERE session=$1
+        "#,
+        session,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let extraction = sqlx::query!(
+        r#"
+        SELECT
+          (SELECT COUNT(*) FROM latest_classifieds c WHERE c.session=$1) AS "classifieds!",
+          (
+            SELECT COUNT(DISTINCT i.url)
+            FROM extraction_issues i
+            WHERE
+              i.session=$1
+              AND i.severity='fatal'
+              AND NOT EXISTS (
+                SELECT 1 FROM classifieds c WHERE c.session=i.session AND c.url=i.url
+              )
+          ) AS "failures!"
+        "#,
+        session,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let extraction_issues = sqlx::query_as!(
+        MessageCount,
+        r#"
+        SELECT
+          COALESCE(field, 'page') || ' (' || severity::TEXT || ')' AS "message!",
+          COUNT(*) AS "count!"
+        FROM extraction_issues
+        WHERE session=$1
+        GROUP BY field, severity
+        ORDER BY 2 DESC, 1
+        "#,
+        session,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let ads = sqlx::query!(
+        r#"
+        SELECT
+          COUNT(*) AS "ads!",
+          COUNT(DISTINCT COALESCE(property_cluster_id, ad_id)) AS "properties!"
+        FROM ads
+        WHERE ad_id IN (SELECT ad_id_from_url(url) FROM crawler_queue WHERE session=$1)
+        "#,
+        session,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let top_errors = sqlx::query_as!(
+        MessageCount,
+        r#"
+        SELECT
+          message AS "message!",
+          COUNT(*) AS "count!"
+        FROM (
+          SELECT failure_error AS message FROM crawler_queue WHERE session=$1
+          UNION ALL
+          SELECT unnest(retries) FROM crawler_queue WHERE session=$1
+        ) errors
+        WHERE message IS NOT NULL
+        GROUP BY message
+        ORDER BY 2 DESC, 1
+        LIMIT $2
+        "#,
+        session,
+        top_errors,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let duration = s.crawled_at.unwrap_or_else(Utc::now) - s.created_at;
+    let minutes = duration.num_seconds() as f64 / 60.0;
+
+    Ok(SessionStats {
+        session: *session,
+        status: s.status,
+        status_reason: s.status_reason,
+        created_at: s.created_at,
+        crawled_at: s.crawled_at,
+        crawl_duration_seconds: duration.num_seconds(),
+        pages_per_minute: match minutes > 0.0 {
+            true => pages.count as f64 / minutes,
+            false => 0.0,
+        },
+        queue,
+        pages: pages.count,
+        page_bytes: pages.bytes,
+        classifieds: extraction.classifieds,
+        extraction_failures: extraction.failures,
+        extraction_issues,
+        ads: ads.ads,
+        properties: ads.properties,
+        top_errors,
+    })
+}
+
+fn print_table(stats: &SessionStats) {
+    println!("session             | {}", stats.session);
+    println!(
+        "status              | {}{}",
+        stats.status,
+        stats
+            .status_reason
+            .as_ref()
+            .map_or(String::new(), |reason| format!(" ({})", reason))
+    );
+    println!("created at          | {}", stats.created_at);
+    println!(
+        "crawled at          | {}",
+        stats
+            .crawled_at
+            .map_or("- (in progress)".into(), |crawled_at| crawled_at.to_string())
+    );
+    println!(
+        "crawl duration      | {}m {}s",
+        stats.crawl_duration_seconds / 60,
+        stats.crawl_duration_seconds % 60
+    );
+    println!("throughput          | {:.2} pages/min", stats.pages_per_minute);
+    println!("pages               | {}", stats.pages);
+    println!(
+        "page bytes          | {} ({:.1} MiB)",
+        stats.page_bytes,
+        stats.page_bytes as f64 / 1024.0 / 1024.0
+    );
+    println!("classifieds         | {}", stats.classifieds);
+    println!("extraction failures | {}", stats.extraction_failures);
+    println!("ads / properties    | {} / {}", stats.ads, stats.properties);
+
+    println!();
+    println!("queue");
+    stats.queue.iter().for_each(|q| {
+        println!("{:<12} | {:<10} | {}", q.page_type.to_string(), q.status, q.count);
+    });
+
+    if !stats.extraction_issues.is_empty() {
+        println!();
+        println!("extraction issues");
+        stats.extraction_issues.iter().for_each(|i| {
+            println!("{:>6} | {}", i.count, i.message);
+        });
+    }
+
+    if !stats.top_errors.is_empty() {
+        println!();
+        println!("top errors");
+        stats.top_errors.iter().for_each(|e| {
+            println!("{:>6} | {}", e.count, e.message);
+        });
+    }
+}
+
+/// Only the single valued stats, one `metric,value` line each.
+fn print_csv(stats: &SessionStats) {
+    println!("{}", csv_row(&["metric", "value"]));
+    [
+        ("session", stats.session.to_string()),
+        ("status", stats.status.to_string()),
+        ("status_reason", stats.status_reason.clone().unwrap_or_default()),
+        ("created_at", stats.created_at.to_rfc3339()),
+        (
+            "crawled_at",
+            stats.crawled_at.map_or(String::new(), |c| c.to_rfc3339()),
+        ),
+        (
+            "crawl_duration_seconds",
+            stats.crawl_duration_seconds.to_string(),
+        ),
+        ("pages_per_minute", format!("{:.2}", stats.pages_per_minute)),
+        ("pages", stats.pages.to_string()),
+        ("page_bytes", stats.page_bytes.to_string()),
+        ("classifieds", stats.classifieds.to_string()),
+        ("extraction_failures", stats.extraction_failures.to_string()),
+        ("ads", stats.ads.to_string()),
+        ("properties", stats.properties.to_string()),
+    ]
+    .iter()
+    .for_each(|(metric, value)| println!("{}", csv_row(&[metric, value.as_str()])));
+}
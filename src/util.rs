@@ -1,7 +1,9 @@
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
-#[derive(serde::Deserialize)]
+#[derive(sqlx::Type, serde::Deserialize, serde::Serialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[sqlx(type_name = "currency")]
 pub enum Currency {
     EUR,
     RON,
@@ -23,13 +25,144 @@ impl TryFrom<&str> for Currency {
 
 pub type PgTransaction<'a> = sqlx::Transaction<'a, sqlx::Postgres>;
 
+/// Holds the name of the signal once one asked us to stop.
+pub type Shutdown = tokio::sync::watch::Receiver<Option<&'static str>>;
+
+/// Listens for SIGINT and SIGTERM, the first one asks the workers to stop
+/// claiming work, a second one exits right away.
+pub fn shutdown_signal() -> anyhow::Result<Shutdown> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut interrupt =
+        signal(SignalKind::interrupt()).context("Failed to listen for SIGINT.")?;
+    let mut terminate =
+        signal(SignalKind::terminate()).context("Failed to listen for SIGTERM.")?;
+    let (sender, receiver) = tokio::sync::watch::channel(None);
+
+    tokio::spawn(async move {
+        let name = tokio::select! {
+            _ = interrupt.recv() => "SIGINT",
+            _ = terminate.recv() => "SIGTERM",
+        };
+        tracing::warn!(
+            "Received {}, finishing in-flight work. Send it again to exit immediately.",
+            name
+        );
+        sender.send(Some(name)).ok();
+
+        tokio::select! {
+            _ = interrupt.recv() => {},
+            _ = terminate.recv() => {},
+        };
+        tracing::warn!("Received a second signal, exiting.");
+        std::process::exit(130);
+    });
+
+    Ok(receiver)
+}
+
+/// Parses durations like `90d`, `12h`, `2w`.
+pub fn parse_duration(s: &str) -> anyhow::Result<chrono::Duration> {
+    let s = s.trim();
+    let unit = s
+        .chars()
+        .last()
+        .ok_or_else(|| anyhow::anyhow!("Empty duration."))?;
+    let amount: i64 = s[..s.len() - unit.len_utf8()]
+        .parse()
+        .with_context(|| format!("Failed to parse duration \"{}\".", s))?;
+
+    match unit {
+        's' => Ok(chrono::Duration::seconds(amount)),
+        'm' => Ok(chrono::Duration::minutes(amount)),
+        'h' => Ok(chrono::Duration::hours(amount)),
+        'd' => Ok(chrono::Duration::days(amount)),
+        'w' => Ok(chrono::Duration::weeks(amount)),
+        u => Err(anyhow::anyhow!(
+            "Unknown duration unit \"{}\", use one of s, m, h, d, w.",
+            u
+        )),
+    }
+}
+
 pub fn try_parse_session(s: &str) -> anyhow::Result<Uuid> {
     let session = Uuid::try_parse(s).context("Failed to parse UUID")?;
     if session
         .get_version()
-        .map_or(true, |v| v != uuid::Version::Random)
+        .is_none_or(|v| v != uuid::Version::Random)
     {
         return Err(anyhow::anyhow!("Only UUID v4 is allowed"));
     };
     Ok(session)
 }
+
+/// How commands print their results, `json` and `csv` are meant for other tools.
+#[derive(clap::ValueEnum, Copy, Clone)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+/// Joins the fields in a CSV line, quoting the ones that need it.
+pub fn csv_row<S: AsRef<str>>(fields: &[S]) -> String {
+    fields
+        .iter()
+        .map(|f| {
+            let f = f.as_ref();
+            match f.contains([',', '"', '\n', '\r']) {
+                true => format!("\"{}\"", f.replace('"', "\"\"")),
+                false => f.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// A point in time given either as a duration ago (`7d`), a date
+/// (`2023-01-31`, midnight UTC) or an RFC 3339 timestamp.
+pub fn parse_since(s: &str) -> anyhow::Result<DateTime<Utc>> {
+    if let Ok(duration) = parse_duration(s) {
+        return Ok(Utc::now() - duration);
+    }
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d") {
+        return Ok(DateTime::from_utc(date.and_hms_opt(0, 0, 0).unwrap(), Utc));
+    }
+    DateTime::parse_from_rfc3339(s.trim())
+        .map(|d| d.with_timezone(&Utc))
+        .with_context(|| format!("Failed to parse \"{}\" as a duration, date or timestamp.", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{csv_row, parse_duration, parse_since};
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90d").unwrap(), chrono::Duration::days(90));
+        assert_eq!(parse_duration(" 12h ").unwrap(), chrono::Duration::hours(12));
+        assert_eq!(parse_duration("2w").unwrap(), chrono::Duration::weeks(2));
+        assert!(parse_duration("90").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3y").is_err());
+    }
+
+    #[test]
+    fn parses_since() {
+        assert_eq!(
+            parse_since("2023-01-31").unwrap().to_rfc3339(),
+            "2023-01-31T00:00:00+00:00"
+        );
+        assert_eq!(
+            parse_since("2023-01-31T10:00:00+02:00").unwrap().to_rfc3339(),
+            "2023-01-31T08:00:00+00:00"
+        );
+        assert!(parse_since("7d").unwrap() < chrono::Utc::now());
+        assert!(parse_since("last week").is_err());
+    }
+
+    #[test]
+    fn quotes_csv_fields() {
+        assert_eq!(csv_row(&["a", "b c", "d,e", "say \"hi\""]), r#"a,b c,"d,e","say ""hi""""#);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::helpers::{classified, crawled_session, crawling_session, item_pages, spawn_app};
+use httpmock::MockServer;
+use olx_scrapie::{
+    ads::refresh_classified_changes,
+    crawler::{budget::CrawlBudget, crawl_session, CrawlOptions},
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+async fn ad(pool: &PgPool, ad_id: &str) -> (String, Uuid, Uuid) {
+    sqlx::query_as("SELECT status::TEXT, first_seen_session, last_seen_session FROM ads WHERE ad_id=$1")
+        .bind(ad_id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn ads_lifecycle_across_sessions() {
+    let app = spawn_app().await;
+
+    let first = crawled_session(
+        &app.pool,
+        "1 day",
+        &[
+            ("https://www.olx.ro/d/oferta/garsoniera-uzina-2-IDgC0Kq.html", "completed"),
+            ("https://www.olx.ro/d/oferta/apartament-2-camere-IDa1b2c.html", "completed"),
+        ],
+    )
+    .await;
+    let second = crawled_session(
+        &app.pool,
+        "1 hour",
+        &[
+            ("https://www.olx.ro/d/oferta/garsoniera-uzina-2-IDgC0Kq.html?reason=observed", "completed"),
+            ("https://www.olx.ro/d/oferta/casa-IDzz9.html", "failed 404"),
+        ],
+    )
+    .await;
+
+    assert_eq!(ad(&app.pool, "olx:gC0Kq").await, ("active".into(), first, second));
+    assert_eq!(ad(&app.pool, "olx:a1b2c").await, ("removed".into(), first, first));
+    assert_eq!(ad(&app.pool, "olx:zz9").await, ("expired".into(), second, second));
+}
+
+#[tokio::test]
+async fn only_gone_pages_expire_ads() {
+    let app = spawn_app().await;
+
+    let first = crawled_session(
+        &app.pool,
+        "1 day",
+        &[
+            ("https://www.olx.ro/d/oferta/garsoniera-uzina-2-IDgC0Kq.html", "completed"),
+            ("https://www.olx.ro/d/oferta/casa-IDzz9.html", "failed 410"),
+        ],
+    )
+    .await;
+    let second = crawled_session(
+        &app.pool,
+        "1 hour",
+        &[
+            ("https://www.olx.ro/d/oferta/garsoniera-uzina-2-IDgC0Kq.html", "failed 503"),
+            ("https://www.olx.ro/d/oferta/casa-IDzz9.html", "failed"),
+            ("https://www.olx.ro/d/oferta/apartament-2-camere-IDa1b2c.html", "failed 429"),
+        ],
+    )
+    .await;
+
+    assert_eq!(ad(&app.pool, "olx:gC0Kq").await, ("active".into(), first, second));
+    assert_eq!(ad(&app.pool, "olx:zz9").await, ("expired".into(), first, second));
+    assert_eq!(ad(&app.pool, "olx:a1b2c").await, ("active".into(), second, second));
+}
+
+#[tokio::test]
+async fn expired_ads_are_not_removed() {
+    let app = spawn_app().await;
+
+    let first = crawled_session(
+        &app.pool,
+        "1 day",
+        &[("https://www.olx.ro/d/oferta/casa-IDzz9.html", "failed 404")],
+    )
+    .await;
+    crawled_session(
+        &app.pool,
+        "1 hour",
+        &[("https://www.olx.ro/d/oferta/garsoniera-uzina-2-IDgC0Kq.html", "completed")],
+    )
+    .await;
+
+    assert_eq!(ad(&app.pool, "olx:zz9").await, ("expired".into(), first, first));
+    let removed_at: Option<chrono::DateTime<chrono::Utc>> =
+        sqlx::query_scalar("SELECT removed_at FROM ads WHERE ad_id='olx:zz9'")
+            .fetch_one(&app.pool)
+            .await
+            .unwrap();
+    assert_eq!(removed_at, None);
+}
+
+#[tokio::test]
+async fn changes_between_observations() {
+    let app = spawn_app().await;
+    let url = "https://www.olx.ro/d/oferta/garsoniera-uzina-2-IDgC0Kq.html";
+
+    let first = crawled_session(&app.pool, "2 days", &[(url, "completed")]).await;
+    let second = crawled_session(&app.pool, "1 day", &[(url, "completed")]).await;
+    let third = crawled_session(&app.pool, "1 hour", &[(url, "completed")]).await;
+    classified(&app.pool, first, url, 450.0, "garsoniera Uzina 2").await;
+    classified(&app.pool, second, url, 450.0, "garsoniera Uzina 2").await;
+    classified(&app.pool, third, url, 2100.0, "garsoniera Uzina 2, negociabil").await;
+    sqlx::query("UPDATE classifieds SET currency='RON' WHERE session=$1")
+        .bind(third)
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+    refresh_classified_changes(&app.pool).await.unwrap();
+
+    let changes: Vec<(Uuid, Uuid, String, String, String)> = sqlx::query_as(
+        "SELECT session, previous_session, field, old_value, new_value
+        FROM classified_changes
+        WHERE ad_id='olx:gC0Kq'
+        ORDER BY field",
+    )
+    .fetch_all(&app.pool)
+    .await
+    .unwrap();
+
+    assert_eq!(
+        changes,
+        vec![
+            (third, second, "currency".into(), "EUR".into(), "RON".into()),
+            (third, second, "price".into(), "450".into(), "2100".into()),
+            (
+                third,
+                second,
+                "title".into(),
+                "garsoniera Uzina 2".into(),
+                "garsoniera Uzina 2, negociabil".into()
+            ),
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn partial_crawls_leave_unseen_ads_alone() {
+    let app = spawn_app().await;
+    let server = MockServer::start();
+    crawled_session(
+        &app.pool,
+        "1 day",
+        &[
+            ("https://www.olx.ro/d/oferta/garsoniera-uzina-2-IDgC0Kq.html", "completed"),
+            ("https://www.olx.ro/d/oferta/apartament-2-camere-IDa1b2c.html", "completed"),
+        ],
+    )
+    .await;
+
+    let (urls, _) = item_pages(&server, 3, std::time::Duration::ZERO);
+
+    let session = crawling_session(&app.pool, &urls).await;
+    let (_signal, shutdown) = tokio::sync::watch::channel(None);
+    let options = CrawlOptions {
+        budget: CrawlBudget {
+            max_item_pages: Some(1),
+            ..CrawlBudget::default()
+        },
+        config: &app.config,
+        pool: app.pool.clone(),
+        session: Some(session),
+        shutdown,
+    };
+    crawl_session(&options, session).await.unwrap();
+
+    let status: String = sqlx::query_scalar("SELECT status::TEXT FROM sessions WHERE session=$1")
+        .bind(session)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "partially_crawled");
+    let statuses: Vec<String> = sqlx::query_scalar("SELECT status::TEXT FROM ads ORDER BY ad_id")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(statuses, vec!["active"; 2]);
+}
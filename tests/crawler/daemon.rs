@@ -0,0 +1,91 @@
+use crate::helpers::spawn_app;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use olx_scrapie::daemon::{parse_searches, Daemon};
+use sqlx::{Connection, PgConnection};
+
+#[tokio::test]
+async fn runs_are_skipped_while_the_search_is_locked() {
+    let app = spawn_app().await;
+    let url = format!("https://www.olx.ro/imobiliare/{}/", uuid::Uuid::new_v4());
+    let searches = parse_searches(&format!(
+        r#"[{{"name": "locked", "url": "{}", "cron": "0 0 * * * *"}}]"#,
+        url
+    ))
+    .unwrap();
+
+    // Another daemon running the same search.
+    let mut lock = PgConnection::connect(app.config.database_url.as_ref())
+        .await
+        .unwrap();
+    let locked: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock(hashtextextended($1, 0))")
+        .bind(searches[0].url.as_str())
+        .fetch_one(&mut lock)
+        .await
+        .unwrap();
+    assert!(locked);
+
+    let (_signal, shutdown) = tokio::sync::watch::channel(None);
+    let daemon = Daemon {
+        config: &app.config,
+        pool: app.pool.clone(),
+        shutdown,
+        workers: 1,
+    };
+    daemon.run_search(&searches[0], Utc::now()).await.unwrap();
+
+    let runs: Vec<(String, String, String, Option<uuid::Uuid>)> =
+        sqlx::query_as("SELECT name, search, status::TEXT, session FROM schedule_runs")
+            .fetch_all(&app.pool)
+            .await
+            .unwrap();
+    assert_eq!(runs, vec![("locked".into(), url, "skipped".into(), None)]);
+
+    let sessions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sessions")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(sessions, 0);
+
+    lock.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn ticks_missed_during_a_run_are_skipped() {
+    let app = spawn_app().await;
+    let searches = parse_searches(
+        r#"[{"name": "busy", "url": "https://www.olx.ro/imobiliare/", "cron": "0 * * * * *"}]"#,
+    )
+    .unwrap();
+
+    let (_signal, shutdown) = tokio::sync::watch::channel(None);
+    let daemon = Daemon {
+        config: &app.config,
+        pool: app.pool.clone(),
+        shutdown,
+        workers: 1,
+    };
+    // A run scheduled at 10:00 that took until 10:03:30.
+    let scheduled_at = Utc.ymd(2023, 1, 24).and_hms(10, 0, 0);
+    let skipped = daemon
+        .skip_missed_ticks(
+            &searches[0],
+            scheduled_at,
+            scheduled_at + Duration::seconds(210),
+        )
+        .await
+        .unwrap();
+    assert_eq!(skipped, 3);
+
+    let runs: Vec<(DateTime<Utc>, String)> = sqlx::query_as(
+        "SELECT scheduled_at, status::TEXT FROM schedule_runs ORDER BY scheduled_at",
+    )
+    .fetch_all(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        runs,
+        (1..=3)
+            .map(|m| (scheduled_at + Duration::minutes(m), "skipped".to_string()))
+            .collect::<Vec<_>>()
+    );
+}
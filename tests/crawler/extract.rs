@@ -0,0 +1,221 @@
+use crate::helpers::spawn_app;
+use olx_scrapie::{
+    config::Config,
+    extract::extractor::{extract, ExtractOptions, EXTRACTOR_VERSION},
+    page::PAGES_SAVED_CHANNEL,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+fn item_url(i: usize) -> String {
+    format!(
+        "https://www.olx.ro/d/oferta/garsoniera-{}-IDgC0K{}.html",
+        i, i
+    )
+}
+
+/// A crawled session with `count` OLX item pages saved, none extracted yet.
+async fn crawled_pages(pool: &PgPool, count: usize) -> Uuid {
+    let session = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO sessions (session, created_at, crawled_at, status)
+        VALUES ($1, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, 'crawled')",
+    )
+    .bind(session)
+    .execute(pool)
+    .await
+    .unwrap();
+
+    let content = std::fs::read_to_string("src/extract/test_assets/olx-item.html").unwrap();
+    for i in 0..count {
+        sqlx::query(
+            "INSERT INTO pages (content, crawled_at, page_type, session, url)
+            VALUES ($1, CURRENT_TIMESTAMP, 'olx_item', $2, $3)",
+        )
+        .bind(&content)
+        .bind(session)
+        .bind(item_url(i))
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+    session
+}
+
+fn options<'a>(
+    config: &'a Config,
+    pool: &PgPool,
+    session: Uuid,
+    workers: usize,
+) -> ExtractOptions<'a> {
+    let (_signal, shutdown) = tokio::sync::watch::channel(None);
+    ExtractOptions {
+        config,
+        follow: false,
+        pool: pool.clone(),
+        reextract_below: None,
+        session,
+        shutdown,
+        workers,
+    }
+}
+
+/// Revisions and extractor versions of the classifieds, by URL.
+async fn revisions(pool: &PgPool) -> Vec<(String, i16, i16)> {
+    sqlx::query_as(
+        "SELECT url, revision, extractor_version FROM classifieds ORDER BY url, revision",
+    )
+    .fetch_all(pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn following_stops_when_the_crawl_is_aborted() {
+    let app = spawn_app().await;
+    let session = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO sessions (session, created_at, status) VALUES ($1, CURRENT_TIMESTAMP, 'crawling')",
+    )
+    .bind(session)
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    let (_signal, shutdown) = tokio::sync::watch::channel(None);
+    let options = ExtractOptions {
+        config: &app.config,
+        follow: true,
+        pool: app.pool.clone(),
+        reextract_below: None,
+        session,
+        shutdown,
+        workers: 1,
+    };
+    let follow = extract(&options);
+    tokio::pin!(follow);
+
+    // Let the worker find nothing and wait for pages.
+    tokio::select! {
+        _ = &mut follow => panic!("Stopped following a crawling session."),
+        _ = tokio::time::sleep(std::time::Duration::from_millis(500)) => {}
+    };
+    sqlx::query("UPDATE sessions SET status='aborted' WHERE session=$1")
+        .bind(session)
+        .execute(&app.pool)
+        .await
+        .unwrap();
+    sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(PAGES_SAVED_CHANNEL)
+        .bind(session.to_string())
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+    let result = tokio::time::timeout(std::time::Duration::from_secs(5), follow)
+        .await
+        .expect("Kept following an aborted crawl.");
+    assert!(result.is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reextraction_adds_revisions_to_outdated_pages() {
+    let app = spawn_app().await;
+    let session = crawled_pages(&app.pool, 2).await;
+    extract(&options(&app.config, &app.pool, session, 1))
+        .await
+        .unwrap();
+    // The first page was extracted by an older extractor.
+    sqlx::query("UPDATE classifieds SET extractor_version=1 WHERE url=$1")
+        .bind(item_url(0))
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+    let reextract = ExtractOptions {
+        reextract_below: Some(EXTRACTOR_VERSION),
+        ..options(&app.config, &app.pool, session, 1)
+    };
+    extract(&reextract).await.unwrap();
+
+    assert_eq!(
+        revisions(&app.pool).await,
+        vec![
+            (item_url(0), 1, 1),
+            (item_url(0), 2, EXTRACTOR_VERSION),
+            (item_url(1), 1, EXTRACTOR_VERSION),
+        ]
+    );
+
+    // Everything is up to date now, nothing to do.
+    extract(&reextract).await.unwrap();
+    assert_eq!(revisions(&app.pool).await.len(), 3);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn workers_never_extract_a_page_twice() {
+    let app = spawn_app().await;
+    let session = crawled_pages(&app.pool, 20).await;
+
+    extract(&options(&app.config, &app.pool, session, 4))
+        .await
+        .unwrap();
+
+    let mut expected: Vec<_> = (0..20)
+        .map(|i| (item_url(i), 1, EXTRACTOR_VERSION))
+        .collect();
+    expected.sort();
+    assert_eq!(revisions(&app.pool).await, expected);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_saves_are_rolled_back_and_reported() {
+    let app = spawn_app().await;
+    let session = crawled_pages(&app.pool, 3).await;
+    sqlx::query(
+        "CREATE FUNCTION reject_classified() RETURNS trigger AS $$
+        BEGIN
+            RAISE EXCEPTION 'rejected';
+        END;
+        $$ LANGUAGE plpgsql",
+    )
+    .execute(&app.pool)
+    .await
+    .unwrap();
+    sqlx::query(&format!(
+        "CREATE TRIGGER reject_classified BEFORE INSERT ON classifieds
+        FOR EACH ROW WHEN (NEW.url = '{}') EXECUTE FUNCTION reject_classified()",
+        item_url(1)
+    ))
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    extract(&options(&app.config, &app.pool, session, 2))
+        .await
+        .unwrap();
+
+    let urls: Vec<_> = revisions(&app.pool)
+        .await
+        .into_iter()
+        .map(|(url, _, _)| url)
+        .collect();
+    assert_eq!(urls, vec![item_url(0), item_url(2)]);
+
+    let issues: Vec<(String, String, String)> =
+        sqlx::query_as("SELECT url, severity::text, message FROM extraction_issues")
+            .fetch_all(&app.pool)
+            .await
+            .unwrap();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].0, item_url(1));
+    assert_eq!(issues[0].1, "fatal");
+    assert!(issues[0].2.contains("Failed saving extraction"));
+
+    let status: (String,) = sqlx::query_as("SELECT status::text FROM sessions WHERE session=$1")
+        .bind(session)
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(status.0, "extracted");
+}
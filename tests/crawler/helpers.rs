@@ -1,4 +1,5 @@
-use olx_scrapie::config::Config;
+use httpmock::{Mock, MockServer};
+use olx_scrapie::{ads::update_ads, config::Config};
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use std::env::var;
 use uuid::Uuid;
@@ -11,6 +12,7 @@ static SETUP: Lazy<()> = Lazy::new(|| {
     };
 });
 
+#[allow(dead_code)]
 pub struct TestApp {
     pub config: Config,
     pub pool: PgPool,
@@ -65,3 +67,134 @@ async fn configure_database() -> (String, PgPool) {
 
     (database_name, db_pool)
 }
+
+const SEARCH: &str = "https://www.olx.ro/imobiliare/apartamente-garsoniere-de-inchiriat/brasov/";
+
+/// A crawled session of `SEARCH` that discovered `items` (url, crawl status),
+/// with its ads updated. Failed items may carry the HTTP status they failed
+/// with, as in "failed 404".
+pub async fn crawled_session(pool: &PgPool, age: &str, items: &[(&str, &str)]) -> Uuid {
+    let session = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO sessions (session, created_at, crawled_at, search)
+        VALUES ($1, CURRENT_TIMESTAMP - $2::INTERVAL, CURRENT_TIMESTAMP, $3)",
+    )
+    .bind(session)
+    .bind(age)
+    .bind(SEARCH)
+    .execute(pool)
+    .await
+    .unwrap();
+
+    for (url, status) in items {
+        let (status, failure_status) = match status.split_once(' ') {
+            Some((status, code)) => (status, Some(code.parse::<i16>().unwrap())),
+            None => (*status, None),
+        };
+        sqlx::query(
+            "INSERT INTO crawler_queue
+            (status, session, url, page_type, added_at, not_before, failure_status)
+            VALUES ($1::crawl_status, $2, $3, 'olx_item', CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, $4)",
+        )
+        .bind(status)
+        .bind(session)
+        .bind(url)
+        .bind(failure_status)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    let mut transaction = pool.begin().await.unwrap();
+    update_ads(&mut transaction, &session).await.unwrap();
+    transaction.commit().await.unwrap();
+
+    session
+}
+
+/// A session of `SEARCH` being crawled, created now, with `urls` queued as new
+/// item jobs. Its ads are not updated yet.
+pub async fn crawling_session(pool: &PgPool, urls: &[String]) -> Uuid {
+    let session = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO sessions (session, created_at, search, status)
+        VALUES ($1, CURRENT_TIMESTAMP, $2, 'crawling')",
+    )
+    .bind(session)
+    .bind(SEARCH)
+    .execute(pool)
+    .await
+    .unwrap();
+
+    for url in urls {
+        sqlx::query(
+            "INSERT INTO crawler_queue (status, session, url, page_type, added_at, not_before)
+            VALUES ('new', $1, $2, 'olx_item', CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)",
+        )
+        .bind(session)
+        .bind(url)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    session
+}
+
+/// `count` distinct OLX item pages served by `server` after `delay`, their
+/// URLs and mocks.
+pub fn item_pages(
+    server: &MockServer,
+    count: usize,
+    delay: std::time::Duration,
+) -> (Vec<String>, Vec<Mock<'_>>) {
+    (0..count)
+        .map(|i| {
+            let path = format!("/d/oferta/garsoniera-{}-IDgC0K{}.html", i, i);
+            let mock = server.mock(|when, then| {
+                when.path(path.as_str());
+                then.delay(delay)
+                    .body_from_file("src/extract/test_assets/olx-item.html");
+            });
+            (server.url(path), mock)
+        })
+        .unzip()
+}
+
+/// A session of `SEARCH` with `count` item pages queued as new jobs, see
+/// `item_pages`.
+pub async fn queued_item_pages<'a>(
+    pool: &PgPool,
+    server: &'a MockServer,
+    count: usize,
+    delay: std::time::Duration,
+) -> (Uuid, Vec<String>, Vec<Mock<'a>>) {
+    let (urls, mocks) = item_pages(server, count, delay);
+    let jobs = urls.iter().map(|u| (u.as_str(), "new")).collect::<Vec<_>>();
+    let session = crawled_session(pool, "1 hour", &jobs).await;
+    (session, urls, mocks)
+}
+
+pub async fn classified(pool: &PgPool, session: Uuid, url: &str, price: f64, title: &str) {
+    sqlx::query(
+        "INSERT INTO pages (content, crawled_at, page_type, session, url)
+        VALUES ('', CURRENT_TIMESTAMP, 'olx_item', $1, $2)",
+    )
+    .bind(session)
+    .bind(url)
+    .execute(pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        "INSERT INTO classifieds
+        (session, url, revision, extracted_at, extractor_version, price, currency, published_at, seller_name, seller_type, title)
+        VALUES ($1, $2, 1, CURRENT_TIMESTAMP, 1, $3, 'EUR', CURRENT_TIMESTAMP, 'Monica', 'private', $4)",
+    )
+    .bind(session)
+    .bind(url)
+    .bind(price)
+    .bind(title)
+    .execute(pool)
+    .await
+    .unwrap();
+}
@@ -0,0 +1,451 @@
+use crate::helpers::{crawled_session, queued_item_pages, spawn_app};
+use chrono::Duration;
+use httpmock::MockServer;
+use olx_scrapie::{
+    config::{QueuePriorities, QueueStrategy, RetryPolicies, RetryPolicy},
+    crawler::{
+        budget::{set_budget, CrawlBudget},
+        job::{process_jobs, CrawlStatus, JobsOutcome},
+        requeue::{requeue_jobs, RequeueFilter},
+    },
+};
+
+fn priorities() -> QueuePriorities {
+    QueuePriorities::of(QueueStrategy::DiscoverFirst)
+}
+
+fn policies(max_attempts: usize) -> RetryPolicies {
+    let policy = RetryPolicy {
+        max_attempts,
+        backoff: Duration::zero(),
+        max_backoff: Duration::zero(),
+    };
+    RetryPolicies {
+        olx_list: policy,
+        olx_item: policy,
+        storia_item: policy,
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn jobs_are_retried_until_the_policy_gives_up() {
+    let app = spawn_app().await;
+    let server = MockServer::start();
+    let ok = server.mock(|when, then| {
+        when.path("/d/oferta/garsoniera-uzina-2-IDgC0Kq.html");
+        then.body_from_file("src/extract/test_assets/olx-item.html");
+    });
+    let flaky = server.mock(|when, then| {
+        when.path("/d/oferta/apartament-2-camere-IDa1b2c.html");
+        then.status(503);
+    });
+    let gone = server.mock(|when, then| {
+        when.path("/d/oferta/apartament-3-camere-IDd3e4f.html");
+        then.status(404);
+    });
+
+    let urls = [
+        server.url("/d/oferta/garsoniera-uzina-2-IDgC0Kq.html"),
+        server.url("/d/oferta/apartament-2-camere-IDa1b2c.html"),
+        server.url("/d/oferta/apartament-3-camere-IDd3e4f.html"),
+    ];
+    let session = crawled_session(
+        &app.pool,
+        "1 hour",
+        &[(&urls[0], "new"), (&urls[1], "new"), (&urls[2], "new")],
+    )
+    .await;
+
+    let (_signal, shutdown) = tokio::sync::watch::channel(None);
+    let outcome = process_jobs(&app.pool, &session, &policies(3), &priorities(), shutdown)
+        .await
+        .unwrap();
+    assert!(matches!(outcome, JobsOutcome::Done));
+
+    ok.assert_hits(1);
+    flaky.assert_hits(3);
+    gone.assert_hits(1);
+
+    let jobs: Vec<(String, String, i32, Option<i16>)> = sqlx::query_as(
+        "SELECT url, status::TEXT, cardinality(retries), failure_status
+        FROM crawler_queue ORDER BY url",
+    )
+    .fetch_all(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        jobs,
+        vec![
+            (urls[1].clone(), "failed".into(), 2, Some(503)),
+            (urls[2].clone(), "failed".into(), 0, Some(404)),
+            (urls[0].clone(), "completed".into(), 0, None),
+        ]
+    );
+
+    let pages: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pages")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(pages, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn interrupted_jobs_stay_queued() {
+    let app = spawn_app().await;
+    let server = MockServer::start();
+    let page = server.mock(|when, then| {
+        when.path("/d/oferta/garsoniera-uzina-2-IDgC0Kq.html");
+        then.status(503);
+    });
+
+    let url = server.url("/d/oferta/garsoniera-uzina-2-IDgC0Kq.html");
+    let session = crawled_session(&app.pool, "1 hour", &[(&url, "new")]).await;
+
+    let (signal, shutdown) = tokio::sync::watch::channel(None);
+    signal.send(Some("SIGINT")).unwrap();
+    let outcome = process_jobs(&app.pool, &session, &policies(3), &priorities(), shutdown)
+        .await
+        .unwrap();
+    assert!(matches!(outcome, JobsOutcome::Interrupted("SIGINT")));
+
+    page.assert_hits(0);
+    let status: String = sqlx::query_scalar("SELECT status::TEXT FROM crawler_queue")
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "new");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn deferred_jobs_wake_up_when_requeued() {
+    let app = spawn_app().await;
+    let server = MockServer::start();
+    let page = server.mock(|when, then| {
+        when.path("/d/oferta/garsoniera-uzina-2-IDgC0Kq.html");
+        then.body_from_file("src/extract/test_assets/olx-item.html");
+    });
+
+    let url = server.url("/d/oferta/garsoniera-uzina-2-IDgC0Kq.html");
+    let session = crawled_session(&app.pool, "1 hour", &[(&url, "retrying")]).await;
+    sqlx::query("UPDATE crawler_queue SET not_before=CURRENT_TIMESTAMP + '1 hour'::INTERVAL")
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+    let (_signal, shutdown) = tokio::sync::watch::channel(None);
+    let pool = app.pool.clone();
+    let crawl = tokio::spawn(async move {
+        process_jobs(&pool, &session, &policies(3), &priorities(), shutdown)
+            .await
+            .unwrap()
+    });
+
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    page.assert_hits(0);
+
+    let mut transaction = app.pool.begin().await.unwrap();
+    let filter = RequeueFilter {
+        status: CrawlStatus::Retrying,
+        page_type: None,
+        error_like: None,
+        keep_retries: true,
+    };
+    requeue_jobs(&mut transaction, &session, &filter)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+
+    let outcome = tokio::time::timeout(std::time::Duration::from_secs(10), crawl)
+        .await
+        .expect("The scheduler slept through the requeued job.")
+        .unwrap();
+    assert!(matches!(outcome, JobsOutcome::Done));
+    page.assert_hits(1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn jobs_of_dead_workers_are_reclaimed() {
+    let app = spawn_app().await;
+    let server = MockServer::start();
+    let page = server.mock(|when, then| {
+        when.path("/d/oferta/garsoniera-uzina-2-IDgC0Kq.html");
+        then.body_from_file("src/extract/test_assets/olx-item.html");
+    });
+
+    let url = server.url("/d/oferta/garsoniera-uzina-2-IDgC0Kq.html");
+    let session = crawled_session(&app.pool, "1 hour", &[(&url, "new")]).await;
+    let dead = uuid::Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO crawler_workers (worker, session, hostname, pid, started_at, heartbeat_at)
+        VALUES ($1, $2, 'gone', 1, CURRENT_TIMESTAMP - '1 hour'::INTERVAL, CURRENT_TIMESTAMP - '1 hour'::INTERVAL)",
+    )
+    .bind(dead)
+    .bind(session)
+    .execute(&app.pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        "UPDATE crawler_queue SET claimed_by=$1, lease_until=CURRENT_TIMESTAMP + '1 hour'::INTERVAL",
+    )
+    .bind(dead)
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    let (_signal, shutdown) = tokio::sync::watch::channel(None);
+    let outcome = tokio::time::timeout(
+        std::time::Duration::from_secs(10),
+        process_jobs(&app.pool, &session, &policies(3), &priorities(), shutdown),
+    )
+    .await
+    .expect("The job of the dead worker was never reclaimed.")
+    .unwrap();
+    assert!(matches!(outcome, JobsOutcome::Done));
+    page.assert_hits(1);
+
+    let job: (String, Option<uuid::Uuid>) =
+        sqlx::query_as("SELECT status::TEXT, claimed_by FROM crawler_queue")
+            .fetch_one(&app.pool)
+            .await
+            .unwrap();
+    assert_eq!(job, ("completed".into(), None));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn concurrent_workers_fetch_every_page_once() {
+    let app = spawn_app().await;
+    let server = MockServer::start();
+    let (session, _, pages) =
+        queued_item_pages(&app.pool, &server, 6, std::time::Duration::from_millis(200)).await;
+
+    let (_signal, shutdown) = tokio::sync::watch::channel(None);
+    let workers = (0..2).map(|_| {
+        let pool = app.pool.clone();
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            process_jobs(&pool, &session, &policies(3), &priorities(), shutdown)
+                .await
+                .unwrap()
+        })
+    });
+    for outcome in futures::future::join_all(workers).await {
+        assert!(matches!(outcome.unwrap(), JobsOutcome::Done));
+    }
+
+    pages.iter().for_each(|page| page.assert_hits(1));
+    let completed: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM crawler_queue WHERE status='completed' AND claimed_by IS NULL",
+    )
+    .fetch_one(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(completed, 6);
+    let stopped: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM crawler_workers WHERE stopped_at IS NOT NULL")
+            .fetch_one(&app.pool)
+            .await
+            .unwrap();
+    assert_eq!(stopped, 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn jobs_are_claimed_by_priority() {
+    let app = spawn_app().await;
+    let server = MockServer::start();
+    let (session, urls, _) =
+        queued_item_pages(&app.pool, &server, 3, std::time::Duration::ZERO).await;
+    for (url, priority) in urls.iter().zip([2i16, 0, 1]) {
+        sqlx::query("UPDATE crawler_queue SET priority=$2 WHERE url=$1")
+            .bind(url)
+            .bind(priority)
+            .execute(&app.pool)
+            .await
+            .unwrap();
+    }
+
+    let (_signal, shutdown) = tokio::sync::watch::channel(None);
+    process_jobs(&app.pool, &session, &policies(3), &priorities(), shutdown)
+        .await
+        .unwrap();
+
+    let crawled: Vec<String> = sqlx::query_scalar("SELECT url FROM pages ORDER BY crawled_at")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        crawled,
+        vec![urls[1].clone(), urls[2].clone(), urls[0].clone()]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn crawls_stop_when_the_budget_runs_out() {
+    let app = spawn_app().await;
+    let server = MockServer::start();
+    let (session, _, _) = queued_item_pages(&app.pool, &server, 4, std::time::Duration::ZERO).await;
+    let blocked = server.mock(|when, then| {
+        when.path_contains("/blocked-");
+        then.status(403);
+    });
+
+    let mut transaction = app.pool.begin().await.unwrap();
+    let budget = CrawlBudget {
+        max_item_pages: Some(2),
+        ..CrawlBudget::default()
+    };
+    set_budget(&mut transaction, &session, &budget)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+
+    let (_signal, shutdown) = tokio::sync::watch::channel(None);
+    let outcome = process_jobs(
+        &app.pool,
+        &session,
+        &policies(3),
+        &priorities(),
+        shutdown.clone(),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(outcome, JobsOutcome::Exhausted(reason) if reason == "Reached 2 item pages."));
+
+    let left: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM crawler_queue WHERE status='new' AND claimed_by IS NULL",
+    )
+    .fetch_one(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(left, 2);
+
+    let blocked_urls = (0..3)
+        .map(|i| server.url(format!("/blocked-{}", i)))
+        .collect::<Vec<_>>();
+    for url in &blocked_urls {
+        sqlx::query(
+            "INSERT INTO crawler_queue (status, session, url, page_type, priority, added_at, not_before)
+            VALUES ('new', $1, $2, 'olx_item', -1, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)",
+        )
+        .bind(session)
+        .bind(url)
+        .execute(&app.pool)
+        .await
+        .unwrap();
+    }
+    let mut transaction = app.pool.begin().await.unwrap();
+    let budget = CrawlBudget {
+        max_consecutive_failures: Some(2),
+        ..CrawlBudget::default()
+    };
+    set_budget(&mut transaction, &session, &budget)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+
+    let outcome = process_jobs(&app.pool, &session, &policies(1), &priorities(), shutdown)
+        .await
+        .unwrap();
+    assert!(
+        matches!(outcome, JobsOutcome::Exhausted(reason) if reason == "2 fetches failed in a row.")
+    );
+    blocked.assert_hits(2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rebudgeted_crawls_count_from_zero() {
+    let app = spawn_app().await;
+    let server = MockServer::start();
+    let (session, _, mocks) =
+        queued_item_pages(&app.pool, &server, 4, std::time::Duration::ZERO).await;
+    let page_bytes = std::fs::metadata("src/extract/test_assets/olx-item.html")
+        .unwrap()
+        .len() as i64;
+    let (_signal, shutdown) = tokio::sync::watch::channel(None);
+
+    let budgets = [
+        CrawlBudget {
+            max_item_pages: Some(2),
+            ..CrawlBudget::default()
+        },
+        // Lifted in between, the counters must not survive it either.
+        CrawlBudget::default(),
+        CrawlBudget {
+            max_item_pages: Some(1),
+            max_bytes: Some(10 * page_bytes),
+            ..CrawlBudget::default()
+        },
+    ];
+    for budget in &budgets {
+        let mut transaction = app.pool.begin().await.unwrap();
+        set_budget(&mut transaction, &session, budget)
+            .await
+            .unwrap();
+        transaction.commit().await.unwrap();
+        if budget.is_unlimited() {
+            continue;
+        }
+
+        let outcome = process_jobs(
+            &app.pool,
+            &session,
+            &policies(3),
+            &priorities(),
+            shutdown.clone(),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(outcome, JobsOutcome::Exhausted(_)));
+    }
+
+    let fetched: usize = mocks.iter().map(|m| m.hits()).sum();
+    assert_eq!(fetched, 3);
+    let usage: (i64, i64) =
+        sqlx::query_as("SELECT item_pages, downloaded_bytes FROM crawl_budgets WHERE session=$1")
+            .bind(session)
+            .fetch_one(&app.pool)
+            .await
+            .unwrap();
+    assert_eq!(usage, (1, page_bytes));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn workers_wait_out_connection_failures() {
+    let app = spawn_app().await;
+    let server = MockServer::start();
+    let (session, urls, mocks) =
+        queued_item_pages(&app.pool, &server, 1, std::time::Duration::from_millis(500)).await;
+
+    // The jobs listener keeps one connection, the other one is taken away
+    // while the page is being fetched.
+    let pool = sqlx::postgres::PgPoolOptions::new()
+        .max_connections(2)
+        .acquire_timeout(std::time::Duration::from_millis(100))
+        .connect_with(app.pool.connect_options().clone())
+        .await
+        .unwrap();
+    let hog = pool.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        let connection = loop {
+            if let Ok(connection) = hog.acquire().await {
+                break connection;
+            }
+        };
+        tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+        drop(connection);
+    });
+
+    let (_signal, shutdown) = tokio::sync::watch::channel(None);
+    let outcome = process_jobs(&pool, &session, &policies(3), &priorities(), shutdown)
+        .await
+        .unwrap();
+    assert!(matches!(outcome, JobsOutcome::Done));
+
+    mocks[0].assert_hits(1);
+    let status: String = sqlx::query_scalar("SELECT status::TEXT FROM crawler_queue WHERE url=$1")
+        .bind(&urls[0])
+        .fetch_one(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "completed");
+}
@@ -1,2 +1,9 @@
 mod helpers;
+mod ads;
+mod daemon;
 mod dummy;
+mod extract;
+mod jobs;
+mod prune;
+mod requeue;
+mod session;
@@ -0,0 +1,93 @@
+use crate::helpers::{classified, crawled_session, spawn_app};
+use olx_scrapie::session::prune::{prune, PrunePolicy};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const EXTRACTED: &str = "https://www.olx.ro/d/oferta/garsoniera-uzina-2-IDgC0Kq.html";
+const PENDING: &str = "https://www.olx.ro/d/oferta/apartament-2-camere-IDa1b2c.html";
+
+/// A crawled session with an extracted page and one still to be extracted.
+async fn session(pool: &PgPool, age: &str) -> Uuid {
+    let session = crawled_session(pool, age, &[]).await;
+    classified(pool, session, EXTRACTED, 450.0, "garsoniera Uzina 2").await;
+    sqlx::query(
+        "INSERT INTO pages (content, crawled_at, page_type, session, url)
+        VALUES ('<html></html>', CURRENT_TIMESTAMP, 'olx_item', $1, $2)",
+    )
+    .bind(session)
+    .bind(PENDING)
+    .execute(pool)
+    .await
+    .unwrap();
+    session
+}
+
+/// The pages that still have their content, by session and URL.
+async fn kept_pages(pool: &PgPool) -> Vec<(Uuid, String)> {
+    sqlx::query_as(
+        "SELECT p.session, p.url
+        FROM pages p
+        JOIN sessions s USING (session)
+        WHERE p.content IS NOT NULL
+        ORDER BY s.created_at, p.url",
+    )
+    .fetch_all(pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn prune_keeps_the_newest_sessions_and_pending_pages() {
+    let app = spawn_app().await;
+    let oldest = session(&app.pool, "3 days").await;
+    let older = session(&app.pool, "2 days").await;
+    let newest = session(&app.pool, "1 day").await;
+    let policy = PrunePolicy {
+        keep_last: Some(1),
+        created_before: None,
+        search: None,
+    };
+
+    let pruned = prune(&app.pool, &policy, false).await.unwrap();
+
+    let mut pruned = pruned
+        .iter()
+        .map(|p| (p.session, p.pages))
+        .collect::<Vec<_>>();
+    pruned.sort();
+    let mut expected = vec![(oldest, 1), (older, 1)];
+    expected.sort();
+    assert_eq!(pruned, expected);
+    assert_eq!(
+        kept_pages(&app.pool).await,
+        vec![
+            (oldest, PENDING.to_string()),
+            (older, PENDING.to_string()),
+            (newest, PENDING.to_string()),
+            (newest, EXTRACTED.to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn dry_runs_prune_nothing() {
+    let app = spawn_app().await;
+    let old = session(&app.pool, "2 days").await;
+    session(&app.pool, "1 day").await;
+    let policy = PrunePolicy {
+        keep_last: Some(1),
+        created_before: None,
+        search: None,
+    };
+
+    let pruned = prune(&app.pool, &policy, true).await.unwrap();
+
+    assert_eq!(
+        pruned
+            .iter()
+            .map(|p| (p.session, p.pages))
+            .collect::<Vec<_>>(),
+        vec![(old, 1)]
+    );
+    assert_eq!(kept_pages(&app.pool).await.len(), 4);
+}
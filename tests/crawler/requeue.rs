@@ -0,0 +1,96 @@
+use crate::helpers::{crawled_session, spawn_app};
+use olx_scrapie::crawler::{
+    job::CrawlStatus,
+    requeue::{requeue_jobs, RequeueFilter},
+};
+
+#[tokio::test]
+async fn requeue_failed_jobs_matching_the_error() {
+    let app = spawn_app().await;
+    let blocked = "https://www.olx.ro/d/oferta/garsoniera-uzina-2-IDgC0Kq.html";
+    let missing = "https://www.olx.ro/d/oferta/apartament-2-camere-IDa1b2c.html";
+    let done = "https://www.olx.ro/d/oferta/apartament-3-camere-IDd3e4f.html";
+
+    let session = crawled_session(
+        &app.pool,
+        "1 hour",
+        &[
+            (blocked, "failed"),
+            (missing, "failed"),
+            (done, "completed"),
+        ],
+    )
+    .await;
+    sqlx::query(
+        "UPDATE crawler_queue
+        SET
+          retries=ARRAY['Status 429 Too Many Requests'],
+          failure_error=CASE WHEN url=$2 THEN 'Status 404 Not Found' ELSE 'Status 429 Too Many Requests' END
+        WHERE session=$1",
+    )
+    .bind(session)
+    .bind(missing)
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    let mut transaction = app.pool.begin().await.unwrap();
+    let filter = RequeueFilter {
+        status: CrawlStatus::Failed,
+        page_type: None,
+        error_like: Some("%404%"),
+        keep_retries: false,
+    };
+    assert_eq!(
+        requeue_jobs(&mut transaction, &session, &filter)
+            .await
+            .unwrap(),
+        1
+    );
+    transaction.commit().await.unwrap();
+
+    let jobs: Vec<(String, String, Vec<String>, Option<String>)> = sqlx::query_as(
+        "SELECT url, status::TEXT, retries, failure_error FROM crawler_queue ORDER BY url",
+    )
+    .fetch_all(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        jobs,
+        vec![
+            (missing.into(), "new".into(), vec![], None),
+            (
+                done.into(),
+                "completed".into(),
+                vec!["Status 429 Too Many Requests".into()],
+                Some("Status 429 Too Many Requests".into())
+            ),
+            (
+                blocked.into(),
+                "failed".into(),
+                vec!["Status 429 Too Many Requests".into()],
+                Some("Status 429 Too Many Requests".into())
+            ),
+        ]
+    );
+
+    // Matches on the failure as well as the earlier attempts.
+    let mut transaction = app.pool.begin().await.unwrap();
+    let filter = RequeueFilter {
+        error_like: Some("%429%"),
+        keep_retries: true,
+        ..filter
+    };
+    assert_eq!(
+        requeue_jobs(&mut transaction, &session, &filter)
+            .await
+            .unwrap(),
+        1
+    );
+    let retries: Vec<String> = sqlx::query_scalar("SELECT retries FROM crawler_queue WHERE url=$1")
+        .bind(blocked)
+        .fetch_one(&mut transaction)
+        .await
+        .unwrap();
+    assert_eq!(retries, vec!["Status 429 Too Many Requests".to_string()]);
+}
@@ -0,0 +1,270 @@
+use crate::helpers::{classified, crawled_session, spawn_app};
+use olx_scrapie::{
+    session::{
+        delete::delete_session,
+        list::{write_sessions, ListSessionsCmd, SortBy},
+        stats::load_stats,
+        SessionStatus,
+    },
+    util::OutputFormat,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// A session created `age` ago, crawled an hour later unless it failed, with
+/// `classifieds` extracted pages.
+async fn session(pool: &PgPool, age: &str, search: &str, status: &str, classifieds: usize) -> Uuid {
+    let session = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO sessions (session, created_at, crawled_at, search, status, status_reason)
+        VALUES (
+          $1,
+          CURRENT_TIMESTAMP - $2::INTERVAL,
+          CASE WHEN $4 <> 'failed' THEN CURRENT_TIMESTAMP - $2::INTERVAL + INTERVAL '1 hour' END,
+          $3,
+          $4::session_status,
+          CASE WHEN $4='failed' THEN 'Blocked, by a captcha.' END
+        )",
+    )
+    .bind(session)
+    .bind(age)
+    .bind(search)
+    .bind(status)
+    .execute(pool)
+    .await
+    .unwrap();
+    for i in 0..classifieds {
+        let url = format!("https://www.olx.ro/d/oferta/garsoniera-{}-IDx{}.html", i, i);
+        classified(pool, session, &url, 450.0, "garsoniera").await;
+    }
+    session
+}
+
+fn list(sort: SortBy, asc: bool) -> ListSessionsCmd {
+    ListSessionsCmd {
+        since: None,
+        search: None,
+        status: None,
+        sort,
+        asc,
+        limit: None,
+        format: OutputFormat::Table,
+    }
+}
+
+async fn listed(pool: &PgPool, cmd: &ListSessionsCmd) -> Vec<Uuid> {
+    cmd.list(pool)
+        .await
+        .unwrap()
+        .iter()
+        .map(|s| s.session)
+        .collect()
+}
+
+#[tokio::test]
+async fn list_sessions_filters_and_sorts() {
+    let app = spawn_app().await;
+    let brasov = "https://www.olx.ro/imobiliare/brasov/";
+    let old = session(&app.pool, "3 days", brasov, "extracted", 2).await;
+    let failed = session(
+        &app.pool,
+        "2 days",
+        "https://www.olx.ro/imobiliare/cluj/",
+        "failed",
+        0,
+    )
+    .await;
+    let new = session(&app.pool, "1 day", brasov, "crawled", 1).await;
+
+    assert_eq!(
+        listed(&app.pool, &list(SortBy::Created, false)).await,
+        vec![new, failed, old]
+    );
+    assert_eq!(
+        listed(&app.pool, &list(SortBy::Created, true)).await,
+        vec![old, failed, new]
+    );
+    assert_eq!(
+        listed(&app.pool, &list(SortBy::Crawled, false)).await,
+        vec![new, old, failed]
+    );
+    assert_eq!(
+        listed(&app.pool, &list(SortBy::Crawled, true)).await,
+        vec![failed, old, new]
+    );
+    assert_eq!(
+        listed(&app.pool, &list(SortBy::Classifieds, false)).await,
+        vec![old, new, failed]
+    );
+
+    let biggest = ListSessionsCmd {
+        limit: Some(2),
+        ..list(SortBy::Pages, false)
+    };
+    assert_eq!(listed(&app.pool, &biggest).await, vec![old, new]);
+
+    let extracted = ListSessionsCmd {
+        status: Some(SessionStatus::Extracted),
+        ..list(SortBy::Created, false)
+    };
+    assert_eq!(listed(&app.pool, &extracted).await, vec![old]);
+
+    let of_brasov = ListSessionsCmd {
+        search: Some("BRASOV".into()),
+        ..list(SortBy::Created, false)
+    };
+    assert_eq!(listed(&app.pool, &of_brasov).await, vec![new, old]);
+}
+
+#[tokio::test]
+async fn list_sessions_as_json_and_csv() {
+    let app = spawn_app().await;
+    let failed = session(
+        &app.pool,
+        "2 days",
+        "https://www.olx.ro/imobiliare/cluj/",
+        "failed",
+        0,
+    )
+    .await;
+    let sessions = list(SortBy::Created, false).list(&app.pool).await.unwrap();
+
+    let mut json = Vec::new();
+    write_sessions(&mut json, &sessions, OutputFormat::Json).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(json[0]["session"], failed.to_string());
+    assert_eq!(json[0]["status"], "failed");
+    assert_eq!(json[0]["status_reason"], "Blocked, by a captcha.");
+    assert_eq!(json[0]["crawled_at"], serde_json::Value::Null);
+    assert_eq!(json[0]["pages"], 0);
+
+    let mut csv = Vec::new();
+    write_sessions(&mut csv, &sessions, OutputFormat::Csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let lines = csv.lines().collect::<Vec<_>>();
+    assert_eq!(
+        lines[0],
+        "session,status,status_reason,created_at,crawled_at,search,pages,classifieds"
+    );
+    assert!(lines[1].starts_with(&format!("{},failed,\"Blocked, by a captcha.\",", failed)));
+    assert!(lines[1].ends_with(",,https://www.olx.ro/imobiliare/cluj/,0,0"));
+    assert_eq!(lines.len(), 2);
+}
+
+#[tokio::test]
+async fn delete_session_keeps_ads_seen_elsewhere() {
+    let app = spawn_app().await;
+    let kept = "https://www.olx.ro/d/oferta/garsoniera-uzina-2-IDgC0Kq.html";
+    let gone = "https://www.olx.ro/d/oferta/apartament-2-camere-IDa1b2c.html";
+
+    let first = crawled_session(&app.pool, "1 day", &[(kept, "completed"), (gone, "completed")]).await;
+    let second = crawled_session(&app.pool, "1 hour", &[(kept, "completed")]).await;
+    classified(&app.pool, first, kept, 450.0, "garsoniera Uzina 2").await;
+
+    let mut transaction = app.pool.begin().await.unwrap();
+    let deleted = delete_session(&mut transaction, &first).await.unwrap();
+    transaction.commit().await.unwrap();
+
+    assert!(deleted.contains(&("classifieds", 1)));
+    assert!(deleted.contains(&("sessions", 1)));
+
+    let ads: Vec<(String, Uuid, Uuid)> = sqlx::query_as(
+        "SELECT ad_id, first_seen_session, last_seen_session FROM ads ORDER BY ad_id",
+    )
+    .fetch_all(&app.pool)
+    .await
+    .unwrap();
+    assert_eq!(ads, vec![("olx:gC0Kq".into(), second, second)]);
+
+    let mut transaction = app.pool.begin().await.unwrap();
+    assert!(delete_session(&mut transaction, &first).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn session_stats_count_the_crawl_and_extraction() {
+    let app = spawn_app().await;
+    let urls: Vec<_> = (0..4)
+        .map(|i| format!("https://www.olx.ro/d/oferta/garsoniera-{}-IDx{}.html", i, i))
+        .collect();
+    let session = crawled_session(
+        &app.pool,
+        "2 hours",
+        &[
+            (&urls[0], "completed"),
+            (&urls[1], "completed"),
+            (&urls[2], "completed"),
+            (&urls[3], "failed 404"),
+        ],
+    )
+    .await;
+    sqlx::query(
+        "UPDATE crawler_queue
+        SET
+          failure_error=CASE WHEN url=$2 THEN 'Not found.' END,
+          retries=CASE WHEN url=$3 THEN ARRAY['Timed out.', 'Not found.'] ELSE retries END
+        WHERE session=$1",
+    )
+    .bind(session)
+    .bind(&urls[3])
+    .bind(&urls[2])
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    classified(&app.pool, session, &urls[0], 450.0, "garsoniera").await;
+    classified(&app.pool, session, &urls[1], 500.0, "garsoniera").await;
+    sqlx::query(
+        "INSERT INTO pages (content, crawled_at, page_type, session, url)
+        VALUES ('<html>', CURRENT_TIMESTAMP, 'olx_item', $1, $2)",
+    )
+    .bind(session)
+    .bind(&urls[2])
+    .execute(&app.pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        "INSERT INTO extraction_issues
+        (session, url, field, severity, message, created_at, revision, extractor_version)
+        VALUES
+          ($1, $2, NULL, 'fatal', 'No classified.', CURRENT_TIMESTAMP, 1, 1),
+          ($1, $3, 'floor', 'warning', 'Unknown floor.', CURRENT_TIMESTAMP, 1, 1)",
+    )
+    .bind(session)
+    .bind(&urls[2])
+    .bind(&urls[0])
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    let stats = load_stats(&app.pool, &session, 1).await.unwrap();
+    assert_eq!(stats.crawl_duration_seconds / 60, 120);
+    assert_eq!(stats.pages, 3);
+    assert_eq!(stats.page_bytes, 6);
+    assert_eq!(stats.classifieds, 2);
+    assert_eq!(stats.extraction_failures, 1);
+    assert_eq!(stats.ads, 4);
+    assert_eq!(stats.properties, 4);
+
+    let json = serde_json::to_value(&stats).unwrap();
+    assert_eq!(json["session"], session.to_string());
+    assert_eq!(json["status"], "created");
+    assert_eq!(
+        json["queue"],
+        serde_json::json!([
+            {"page_type": "olx_item", "status": "completed", "count": 3},
+            {"page_type": "olx_item", "status": "failed", "count": 1},
+        ])
+    );
+    assert_eq!(
+        json["extraction_issues"],
+        serde_json::json!([
+            {"message": "floor (warning)", "count": 1},
+            {"message": "page (fatal)", "count": 1},
+        ])
+    );
+    // The final failure and one of the retries share a message.
+    assert_eq!(
+        json["top_errors"],
+        serde_json::json!([{"message": "Not found.", "count": 2}])
+    );
+}